@@ -19,7 +19,12 @@
 //! currency = "USD"
 //! ```
 //!
-//! An optional opening balance can be included.
+//! An optional opening balance can be included. It is recorded as a transaction
+//! on the opening date against the `Opening Balances` equity account, or a
+//! `Opening Balances:<currency>` sub-account for other currencies, which are
+//! declared if needed. An equity account that is not declared takes the
+//! currency of the first opening balance. The equity account can be changed in
+//! the ledger options.
 //!
 //! ```toml
 //! [[account]]
@@ -30,10 +35,32 @@
 //! opening_balance = 1000.00 # optional
 //! ```
 //!
+//! Accounts that are no longer used can be closed. No postings are allowed
+//! after the closing date and the account balance must be zero by then.
+//!
+//! ```toml
+//! [[account]]
+//! open = 2023-09-30
+//! close = 2024-03-31 # optional
+//! name = "Old Savings Account"
+//! type = "Assets"
+//! currency = "USD"
+//! ```
+//!
+//! Account names can be colon separated paths, like `Food:Dining` and
+//! `Food:Groceries`. Reports show them as a hierarchy where each parent node
+//! holds the subtotal of its children, even if the parent itself is not declared.
+//!
+//! Stocks, MutualFunds and Holdings accounts keep the lots of the units bought,
+//! sold in the order of the optional `booking` method. See [crate::lots].
+//!
+use crate::error::Location;
+use crate::lots::Booking;
 use chrono::prelude::*;
+use rust_decimal::Decimal;
 use std::{fmt, str::FromStr};
 
-#[derive(Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum AccountType {
     Assets,
     Income,
@@ -64,6 +91,38 @@ impl fmt::Display for AccountType {
     }
 }
 
+impl AccountType {
+    /// Returns true for the types reported in the balance sheet, whose balances
+    /// carry over between periods. Income and expenses are flows that start
+    /// from zero every period.
+    pub fn is_balance_sheet(&self) -> bool {
+        !matches!(
+            self,
+            AccountType::Income | AccountType::Expenses | AccountType::Unknown
+        )
+    }
+
+    /// Returns true for the types reported as assets in the balance sheet.
+    pub fn is_asset(&self) -> bool {
+        matches!(
+            self,
+            AccountType::Assets
+                | AccountType::Stocks
+                | AccountType::MutualFunds
+                | AccountType::Holdings
+                | AccountType::Cash
+        )
+    }
+
+    /// Returns true for the types whose units are kept as lots with their cost.
+    pub fn tracks_lots(&self) -> bool {
+        matches!(
+            self,
+            AccountType::Stocks | AccountType::MutualFunds | AccountType::Holdings
+        )
+    }
+}
+
 impl FromStr for AccountType {
     type Err = ();
     fn from_str(input: &str) -> Result<AccountType, Self::Err> {
@@ -88,7 +147,11 @@ pub struct Account {
     pub open: NaiveDate,
     pub currency: String,
     pub account_type: AccountType,
-    pub opening_balance: Option<f32>,
+    pub opening_balance: Option<Decimal>,
+    pub close: Option<NaiveDate>,
+    /// Order in which sales take units from the lots of the account.
+    pub booking: Booking,
+    pub location: Option<Location>,
 }
 
 impl Default for Account {
@@ -99,6 +162,9 @@ impl Default for Account {
             currency: String::from("USD"),
             account_type: AccountType::Assets,
             opening_balance: None,
+            close: None,
+            booking: Booking::Fifo,
+            location: None,
         }
     }
 }
@@ -109,18 +175,118 @@ impl Account {
         open: NaiveDate,
         currency: String,
         account_type: AccountType,
-        opening_balance: Option<f32>,
+        opening_balance: Option<Decimal>,
     ) -> Self {
         Self {
             name: name.replace('"', ""),
-            open: open,
+            open,
             currency: currency.replace('"', ""),
-            account_type: account_type,
-            opening_balance: opening_balance,
+            account_type,
+            opening_balance,
+            close: None,
+            booking: Booking::Fifo,
+            location: None,
         }
     }
 }
 
+impl Account {
+    /// Returns true if the account is closed on the given date. Postings are
+    /// allowed on the closing date itself.
+    pub fn is_closed(&self, date: NaiveDate) -> bool {
+        self.close.is_some_and(|c| c < date)
+    }
+
+    /// Returns true if the account is open at some point between the dates.
+    pub fn is_active(&self, from: NaiveDate, to: NaiveDate) -> bool {
+        self.open <= to && self.close.is_none_or(|c| c >= from)
+    }
+
+    /// The segments of the colon separated account name.
+    pub fn segments(&self) -> Vec<&str> {
+        self.name.split(ACCOUNT_SEPARATOR).collect()
+    }
+
+    /// Returns true if the account is the given path or is nested under it.
+    pub fn is_under(&self, path: &str) -> bool {
+        is_under(&self.name, path)
+    }
+}
+
+/// Separator of the segments of hierarchical account names.
+pub const ACCOUNT_SEPARATOR: char = ':';
+
+/// Returns true if the account name is the given path or is nested under it.
+pub fn is_under(name: &str, path: &str) -> bool {
+    name == path
+        || name
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with(ACCOUNT_SEPARATOR))
+}
+
+/// A node of the account hierarchy. Nodes for parent paths that are not
+/// declared as accounts are created as needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountNode {
+    /// Full colon separated path of the node.
+    pub name: String,
+    /// Nesting level, starting at zero for top level nodes.
+    pub depth: usize,
+    pub children: Vec<AccountNode>,
+}
+
+impl AccountNode {
+    /// The last segment of the path.
+    pub fn segment(&self) -> &str {
+        self.name
+            .rsplit(ACCOUNT_SEPARATOR)
+            .next()
+            .unwrap_or_default()
+    }
+
+    /// The node and its descendants in display order, down to `max_depth`
+    /// levels (all levels if None).
+    pub fn flatten(&self, max_depth: Option<usize>) -> Vec<&AccountNode> {
+        let mut nodes = Vec::new();
+        if max_depth.is_none_or(|d| self.depth < d) {
+            nodes.push(self);
+            for c in &self.children {
+                nodes.extend(c.flatten(max_depth));
+            }
+        }
+        nodes
+    }
+}
+
+/// Builds the account hierarchy from a list of account names. Nodes keep the
+/// order in which they first appear.
+pub fn account_tree<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<AccountNode> {
+    let mut roots: Vec<AccountNode> = Vec::new();
+    for name in names {
+        let mut level = &mut roots;
+        let mut path = String::new();
+        for (depth, segment) in name.split(ACCOUNT_SEPARATOR).enumerate() {
+            if depth > 0 {
+                path.push(ACCOUNT_SEPARATOR);
+            }
+            path.push_str(segment);
+            let i = match level.iter().position(|n| n.name == path) {
+                Some(i) => i,
+                None => {
+                    level.push(AccountNode {
+                        name: path.clone(),
+                        depth,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            level = &mut level[i].children;
+        }
+    }
+    roots
+}
+
 impl fmt::Display for Account {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(
@@ -143,6 +309,23 @@ mod tests {
         assert_eq!(account.account_type, AccountType::Assets);
         assert_eq!(account.open, Local::now().date_naive());
         assert_eq!(account.opening_balance, None);
+        assert_eq!(account.close, None);
+    }
+
+    #[test]
+    fn test_account_lifecycle() {
+        let date = |d| NaiveDate::from_ymd_opt(2023, 10, d).unwrap();
+        let account = Account {
+            open: date(1),
+            close: Some(date(20)),
+            ..Default::default()
+        };
+
+        assert!(!account.is_closed(date(20)));
+        assert!(account.is_closed(date(21)));
+        assert!(account.is_active(date(20), date(25)));
+        assert!(!account.is_active(date(21), date(25)));
+        assert!(!Account::default().is_closed(date(31)));
     }
 
     #[test]
@@ -151,7 +334,7 @@ mod tests {
         let open = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let currency = "EUR".to_string();
         let account_type = AccountType::Income;
-        let opening_balance = Some(1000.0);
+        let opening_balance = Some(Decimal::from(1000));
 
         let account = Account::new(
             name.clone(),
@@ -168,6 +351,58 @@ mod tests {
         assert_eq!(account.opening_balance, opening_balance);
     }
 
+    #[test]
+    fn test_account_hierarchy() {
+        let account = Account {
+            name: "Food:Dining".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(account.segments(), vec!["Food", "Dining"]);
+        assert!(account.is_under("Food"));
+        assert!(account.is_under("Food:Dining"));
+        assert!(!account.is_under("Foo"));
+    }
+
+    #[test]
+    fn test_account_tree() {
+        let tree = account_tree(["Food:Dining", "Cash", "Food:Groceries:Fruit"]);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].name, "Food");
+        assert_eq!(tree[0].children[1].segment(), "Groceries");
+
+        let names: Vec<&str> = tree[0]
+            .flatten(None)
+            .iter()
+            .map(|n| n.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "Food",
+                "Food:Dining",
+                "Food:Groceries",
+                "Food:Groceries:Fruit"
+            ]
+        );
+        assert_eq!(tree[0].flatten(Some(2)).len(), 3);
+        assert_eq!(tree[0].flatten(Some(0)).len(), 0);
+    }
+
+    #[test]
+    fn test_account_type_is_balance_sheet() {
+        assert!(AccountType::Assets.is_balance_sheet());
+        assert!(AccountType::Stocks.is_balance_sheet());
+        assert!(AccountType::Liabilities.is_balance_sheet());
+        assert!(!AccountType::Income.is_balance_sheet());
+        assert!(!AccountType::Expenses.is_balance_sheet());
+        assert!(AccountType::Cash.is_asset());
+        assert!(!AccountType::Equity.is_asset());
+        assert!(AccountType::MutualFunds.tracks_lots());
+        assert!(!AccountType::Assets.tracks_lots());
+    }
+
     #[test]
     fn test_account_to_enum() {
         assert_eq!(
@@ -0,0 +1,129 @@
+//! This module defines the [BalanceAssertion] and [Pad] structs.
+//!
+//! A balance assertion states the balance an account must hold at the end of
+//! a given day. The `check` command compares it with the balance computed from
+//! the transactions, which is the main safeguard against import mistakes.
+//! An optional **tolerance** allows small rounding differences.
+//!
+//! ```toml
+//! [[balance]]
+//! date = 2023-10-31
+//! account = "Savings Account"
+//! amount = 3245.00
+//! tolerance = 0.01 # optional
+//! ```
+//!
+//! A pad entry fills the gap between the computed balance of an account and
+//! its next balance assertion with a generated transaction against a
+//! **source** account, which is useful to start tracking an account mid-history.
+//!
+//! ```toml
+//! [[pad]]
+//! date = 2023-01-01
+//! account = "Savings Account"
+//! source = "Opening Balances"
+//! ```
+
+use crate::error::Location;
+use chrono::prelude::*;
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceAssertion {
+    pub date: NaiveDate,
+    pub account: String,
+    pub amount: Decimal,
+    pub tolerance: Decimal,
+    pub location: Option<Location>,
+}
+
+impl BalanceAssertion {
+    pub fn new(date: NaiveDate, account: String, amount: Decimal, tolerance: Decimal) -> Self {
+        Self {
+            date,
+            account: account.replace('"', ""),
+            amount,
+            tolerance,
+            location: None,
+        }
+    }
+
+    /// Returns true if the actual balance is within the tolerance of the
+    /// asserted amount.
+    pub fn holds(&self, actual: Decimal) -> bool {
+        (actual - self.amount).abs() <= self.tolerance
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pad {
+    pub date: NaiveDate,
+    pub account: String,
+    pub source: String,
+    pub location: Option<Location>,
+}
+
+impl Pad {
+    pub fn new(date: NaiveDate, account: String, source: String) -> Self {
+        Self {
+            date,
+            account: account.replace('"', ""),
+            source: source.replace('"', ""),
+            location: None,
+        }
+    }
+
+    /// The first assertion of the padded account dated on or after the pad.
+    pub fn next_assertion<'a>(
+        &self,
+        assertions: &'a [BalanceAssertion],
+    ) -> Option<&'a BalanceAssertion> {
+        assertions
+            .iter()
+            .filter(|b| b.account == self.account && b.date >= self.date)
+            .min_by_key(|b| b.date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_balance_assertion_holds() {
+        let date = NaiveDate::from_ymd_opt(2023, 10, 31).unwrap();
+        let amount = Decimal::from_str("3245.00").unwrap();
+
+        let exact = BalanceAssertion::new(date, "Savings".to_string(), amount, Decimal::ZERO);
+        assert!(exact.holds(Decimal::from(3245)));
+        assert!(!exact.holds(Decimal::from_str("3245.01").unwrap()));
+
+        let tolerance = Decimal::from_str("0.01").unwrap();
+        let rounded = BalanceAssertion::new(date, "Savings".to_string(), amount, tolerance);
+        assert!(rounded.holds(Decimal::from_str("3244.99").unwrap()));
+        assert!(!rounded.holds(Decimal::from_str("3244.98").unwrap()));
+    }
+
+    #[test]
+    fn test_pad_next_assertion() {
+        let date = |d| NaiveDate::from_ymd_opt(2023, 10, d).unwrap();
+        let assertion = |d, account: &str| {
+            BalanceAssertion::new(date(d), account.to_string(), Decimal::ZERO, Decimal::ZERO)
+        };
+        let assertions = vec![
+            assertion(1, "Savings"),
+            assertion(20, "Savings"),
+            assertion(10, "Savings"),
+            assertion(5, "Checking"),
+        ];
+        let pad = Pad::new(date(2), "Savings".to_string(), "Equity".to_string());
+
+        assert_eq!(pad.next_assertion(&assertions), Some(&assertions[2]));
+        assert_eq!(
+            Pad::new(date(21), "Savings".to_string(), "Equity".to_string())
+                .next_assertion(&assertions),
+            None
+        );
+    }
+}
@@ -1,23 +1,66 @@
-//! This module is the csv transaction importer.
+//! This module is the csv transaction and price importer.
 
+use crate::price::Price;
 use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashSet;
 use std::error::Error;
-use std::fs::OpenOptions;
-use std::io::Write;
-use toml::to_string_pretty;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::str::FromStr;
+use toml::Value;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize)]
 struct CsvRow {
     #[serde(rename = "date")]
     date_str: String,
     account: String,
     payee: Option<String>,
-    quantity: Option<f32>,
-    amount: f32,
+    #[serde(
+        deserialize_with = "rust_decimal::serde::str_option::deserialize",
+        default
+    )]
+    quantity: Option<Decimal>,
+    // amounts are read and written as their decimal text
+    #[serde(deserialize_with = "rust_decimal::serde::str::deserialize")]
+    amount: Decimal,
     offset_account: Option<String>,
-    offset_amount: Option<f32>,
+    #[serde(
+        deserialize_with = "rust_decimal::serde::str_option::deserialize",
+        default
+    )]
+    offset_amount: Option<Decimal>,
 }
+
+impl CsvRow {
+    /// The row as a toml `[[transaction]]` entry, leaving out missing fields.
+    fn entry(&self) -> String {
+        let mut entry = format!(
+            "\n[[transaction]]\ndate = {}\naccount = {}\n",
+            self.date_str,
+            Value::String(self.account.clone())
+        );
+        if let Some(payee) = &self.payee {
+            entry.push_str(&format!("payee = {}\n", Value::String(payee.clone())));
+        }
+        if let Some(quantity) = self.quantity {
+            entry.push_str(&format!("quantity = {}\n", quantity));
+        }
+        entry.push_str(&format!("amount = {}\n", self.amount));
+        if let Some(offset_account) = &self.offset_account {
+            entry.push_str(&format!(
+                "offset_account = {}\n",
+                Value::String(offset_account.clone())
+            ));
+        }
+        if let Some(offset_amount) = self.offset_amount {
+            entry.push_str(&format!("offset_amount = {}\n", offset_amount));
+        }
+        entry
+    }
+}
+
 pub fn import_transactions(
     csv_file: &str,
     toml_file: &str,
@@ -36,8 +79,7 @@ pub fn import_transactions(
         row.date_str = date.to_string();
         row.amount = row.amount.abs();
         row.quantity = None;
-        row.offset_account = row.offset_account;
-        row.offset_amount = Some(row.amount * -1.0);
+        row.offset_amount = Some(-row.amount);
 
         new_transactions.push(row);
     }
@@ -50,12 +92,214 @@ pub fn import_transactions(
     println!("Import start");
     for t in new_transactions {
         println!("Imported: {:?}", t);
-        let toml_value = to_string_pretty(&t)?;
-        file.write("\n[[transaction]]\n".as_bytes())?;
-        file.write_all(toml_value.as_bytes())
-            .expect("Failed to write to file");
+        file.write_all(t.entry().as_bytes())?;
     }
     println!("Import complete");
 
     Ok(())
 }
+
+/// Layout of a csv file of prices. Files without a commodity or currency
+/// column, like the price history of a single stock, take them from
+/// `commodity` and `currency`.
+#[derive(Debug, Clone)]
+pub struct PriceCsvFormat {
+    pub date_column: String,
+    pub commodity_column: String,
+    pub price_column: String,
+    pub currency_column: String,
+    pub commodity: Option<String>,
+    pub currency: Option<String>,
+    pub date_format: String,
+}
+
+impl Default for PriceCsvFormat {
+    fn default() -> Self {
+        Self {
+            date_column: "date".to_string(),
+            commodity_column: "commodity".to_string(),
+            price_column: "price".to_string(),
+            currency_column: "currency".to_string(),
+            commodity: None,
+            currency: None,
+            date_format: "%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+/// Reads the prices of a csv file.
+pub fn read_prices<R: io::Read>(
+    reader: R,
+    format: &PriceCsvFormat,
+) -> Result<Vec<Price>, Box<dyn Error>> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let column = |name: &str, fixed: bool| match headers.iter().position(|h| h == name) {
+        Some(i) => Ok(Some(i)),
+        None if fixed => Ok(None),
+        None => Err(format!("Column {} not found in the csv file", name)),
+    };
+    let date_column = column(&format.date_column, false)?;
+    let price_column = column(&format.price_column, false)?;
+    let commodity_column = column(&format.commodity_column, format.commodity.is_some())?;
+    let currency_column = column(&format.currency_column, format.currency.is_some())?;
+
+    let mut prices = Vec::new();
+    for (i, result) in rdr.records().enumerate() {
+        let record = result?;
+        let field = |column: Option<usize>, fixed: &Option<String>| {
+            column
+                .and_then(|c| record.get(c))
+                .map(|v| v.trim().to_string())
+                .or(fixed.clone())
+                .unwrap_or_default()
+        };
+        let line = i + 2;
+        let date = NaiveDate::parse_from_str(&field(date_column, &None), &format.date_format)
+            .map_err(|e| format!("Invalid date in line {}: {}", line, e))?;
+        let price = Decimal::from_str(&field(price_column, &None))
+            .map_err(|e| format!("Invalid price in line {}: {}", line, e))?;
+        let commodity = field(commodity_column, &format.commodity);
+        let currency = field(currency_column, &format.currency);
+        prices.push(Price::new(date, commodity, price, currency));
+    }
+    Ok(prices)
+}
+
+/// Appends the prices of a csv file to a toml file as `[[price]]` entries.
+/// Prices of a commodity and currency on a date already in `existing`, or
+/// earlier in the csv file, are skipped.
+pub fn import_prices(
+    csv_file: &str,
+    toml_file: &str,
+    existing: &[Price],
+    format: &PriceCsvFormat,
+) -> Result<(), Box<dyn Error>> {
+    let prices = read_prices(File::open(csv_file)?, format)?;
+    append_prices(toml_file, prices, existing)
+}
+
+/// Appends the prices to a toml file as `[[price]]` entries, skipping the
+/// prices of a commodity and currency on a date already in `existing` or
+/// earlier in the list.
+pub fn append_prices(
+    toml_file: &str,
+    prices: Vec<Price>,
+    existing: &[Price],
+) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(toml_file)?;
+
+    let key = |p: &Price| (p.date, p.commodity.clone(), p.currency.clone());
+    let mut declared: HashSet<_> = existing.iter().map(key).collect();
+
+    println!("Import start");
+    for p in prices {
+        let description = format!("{} {} {} {}", p.date, p.commodity, p.price, p.currency);
+        if !declared.insert(key(&p)) {
+            println!("Skipped: {}", description);
+            continue;
+        }
+        println!("Imported: {}", description);
+        let entry = format!(
+            "\n[[price]]\ndate = {}\ncommodity = {}\nprice = {}\ncurrency = {}\n",
+            p.date,
+            Value::String(p.commodity),
+            p.price,
+            Value::String(p.currency)
+        );
+        file.write_all(entry.as_bytes())?;
+    }
+    println!("Import complete");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::Ledger;
+    use std::fs;
+
+    #[test]
+    fn test_csv_row_amounts() {
+        let csv = "date,account,payee,quantity,amount,offset_account,offset_amount\n\
+                   02/10/2023,Dining,,,1234567.891,Savings Account,\n";
+        let mut rdr = csv::Reader::from_reader(csv.as_bytes());
+        let row: CsvRow = rdr.deserialize().next().unwrap().unwrap();
+
+        assert_eq!(row.amount, Decimal::new(1234567891, 3));
+        assert_eq!(row.quantity, None);
+        assert_eq!(row.offset_amount, None);
+
+        let row = CsvRow {
+            date_str: "2023-10-02".to_string(),
+            offset_amount: Some(Decimal::new(-12345678901234567, 10)),
+            ..row
+        };
+        let expected = "
+[[transaction]]
+date = 2023-10-02
+account = \"Dining\"
+amount = 1234567.891
+offset_account = \"Savings Account\"
+offset_amount = -1234567.8901234567
+";
+        assert_eq!(row.entry(), expected);
+    }
+
+    #[test]
+    fn test_read_prices() {
+        let csv = "Date,Open,Close\n29/09/2023,388.10,390.50\n02/10/2023,390.50,391.00\n";
+        let format = PriceCsvFormat {
+            date_column: "Date".to_string(),
+            price_column: "Close".to_string(),
+            commodity: Some("VOO".to_string()),
+            currency: Some("USD".to_string()),
+            date_format: "%d/%m/%Y".to_string(),
+            ..Default::default()
+        };
+        let prices = read_prices(csv.as_bytes(), &format).unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(
+            prices[0].date,
+            NaiveDate::from_ymd_opt(2023, 9, 29).unwrap()
+        );
+        assert_eq!(prices[0].commodity, "VOO");
+        assert_eq!(prices[1].price, Decimal::new(39100, 2));
+
+        let missing = read_prices(csv.as_bytes(), &PriceCsvFormat::default());
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_import_prices_skips_declared_prices() {
+        let dir = std::env::temp_dir().join("abacus-import-prices");
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("prices.csv");
+        let toml = dir.join("prices.toml");
+        fs::write(
+            &csv,
+            "date,commodity,price,currency\n\
+             2023-10-02,USD,930.00,ARS\n\
+             2023-10-02,USD,930.00,ARS\n\
+             2023-10-03,USD,935.50,ARS\n",
+        )
+        .unwrap();
+        fs::write(&toml, "").unwrap();
+        let (csv, toml) = (csv.to_str().unwrap(), toml.to_str().unwrap());
+
+        import_prices(csv, toml, &[], &PriceCsvFormat::default()).unwrap();
+        let ledger = Ledger::new(&fs::read_to_string(toml).unwrap()).unwrap();
+        assert_eq!(ledger.prices().len(), 2);
+        assert_eq!(ledger.prices()[1].price, Decimal::new(93550, 2));
+
+        import_prices(csv, toml, ledger.prices(), &PriceCsvFormat::default()).unwrap();
+        let ledger = Ledger::new(&fs::read_to_string(toml).unwrap()).unwrap();
+        assert_eq!(ledger.prices().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
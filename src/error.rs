@@ -0,0 +1,371 @@
+//! This module defines the [LedgerError] type.
+//!
+//! Errors point to the file, line and column of the offending `[[account]]`,
+//! `[[transaction]]`, `[[price]]`, `[[balance]]`, `[[pad]]` or `[[price_source]]` table and include a snippet of the entry,
+//! so they can be fixed without reading a backtrace.
+//!
+//! ```bash
+//! error: Account "Dinning" does not exist
+//!   --> example/transactions.toml:8:1
+//!    |
+//!  8 | [[transaction]]
+//!  9 | date = 2023-10-11
+//! 10 | account = "Dinning"
+//! ```
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// Maximum number of lines of an entry shown in an error snippet.
+const SNIPPET_LINES: usize = 6;
+
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl Location {
+    /// Builds a location from a byte span of the source file content.
+    pub fn from_span(file: &str, content: &str, span: Range<usize>) -> Self {
+        let start = span.start.min(content.len());
+        let end = span.end.clamp(start, content.len());
+        let before = &content[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        let snippet = match content[start..end].trim_end() {
+            "" => content[line_start..]
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            entry => entry.to_string(),
+        };
+
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            snippet,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = (self.line + SNIPPET_LINES).to_string().len();
+        let mut lines = vec![
+            format!(
+                "{:>width$}--> {}:{}:{}",
+                "", self.file, self.line, self.column
+            ),
+            format!("{:>width$} |", ""),
+        ];
+        for (i, l) in self.snippet.lines().take(SNIPPET_LINES).enumerate() {
+            lines.push(format!("{:>width$} | {}", self.line + i, l));
+        }
+        if self.snippet.lines().count() > SNIPPET_LINES {
+            lines.push(format!("{:>width$} | ...", ""));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LedgerError {
+    /// A ledger file could not be read.
+    Io { path: String, message: String },
+    /// A ledger file is not valid toml.
+    Syntax { location: Location, message: String },
+    /// A required field of an entry is missing.
+    MissingField { location: Location, field: String },
+    /// A field of an entry has the wrong type or format.
+    InvalidValue {
+        location: Location,
+        field: String,
+        message: String,
+    },
+    /// A posting refers to an account that is not declared.
+    UnknownAccount { location: Location, account: String },
+    /// An account name is declared more than once.
+    DuplicateAccount { location: Location, account: String },
+    /// An account is declared with a type that is not known.
+    UnknownAccountType { location: Location, account: String },
+    /// A posting is dated before the account is opened.
+    BeforeOpen {
+        location: Location,
+        account: String,
+        open: NaiveDate,
+    },
+    /// A posting is dated after the account is closed.
+    AfterClose {
+        location: Location,
+        account: String,
+        close: NaiveDate,
+    },
+    /// An account is closed while it still holds a balance.
+    ClosedWithBalance {
+        location: Location,
+        account: String,
+        close: NaiveDate,
+        balance: Decimal,
+    },
+    /// A balance assertion does not match the computed balance.
+    BalanceMismatch {
+        location: Location,
+        account: String,
+        date: NaiveDate,
+        expected: Decimal,
+        actual: Decimal,
+    },
+    /// A pad has no balance assertion after it for the padded account.
+    PadWithoutBalance { location: Location, account: String },
+    /// A price is declared for a commodity no account is held in.
+    UnknownCommodity {
+        location: Location,
+        commodity: String,
+    },
+    /// The postings of a single currency transaction do not sum zero.
+    Unbalanced {
+        location: Location,
+        difference: Decimal,
+    },
+    /// The postings of a transaction do not sum zero at their exchange rates.
+    RateMismatch {
+        location: Location,
+        currency: String,
+        difference: Decimal,
+    },
+    /// A transaction between accounts in different currencies declares no
+    /// exchange rate.
+    MissingRate {
+        location: Location,
+        currencies: Vec<String>,
+    },
+    /// The cost of the units bought into a lot account can not be determined.
+    MissingCost { location: Location, account: String },
+    /// A sale takes more units than the lots of the account hold.
+    NotEnoughUnits {
+        location: Location,
+        account: String,
+        quantity: Decimal,
+        available: Decimal,
+    },
+    /// A price source failed to price a commodity.
+    PriceSource {
+        location: Location,
+        commodity: String,
+        message: String,
+    },
+    /// All the problems found in the ledger.
+    Multiple(Vec<LedgerError>),
+}
+
+impl LedgerError {
+    /// Turns a list of problems into a single result.
+    pub fn from_errors(mut errors: Vec<LedgerError>) -> Result<(), LedgerError> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(LedgerError::Multiple(errors)),
+        }
+    }
+
+    /// Flattens nested errors into a list of single problems.
+    pub fn errors(&self) -> Vec<&LedgerError> {
+        match self {
+            LedgerError::Multiple(errors) => errors.iter().flat_map(|e| e.errors()).collect(),
+            e => vec![e],
+        }
+    }
+
+    /// The location of the problem, if it refers to a ledger entry.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            LedgerError::Syntax { location, .. }
+            | LedgerError::MissingField { location, .. }
+            | LedgerError::InvalidValue { location, .. }
+            | LedgerError::UnknownAccount { location, .. }
+            | LedgerError::DuplicateAccount { location, .. }
+            | LedgerError::UnknownAccountType { location, .. }
+            | LedgerError::BeforeOpen { location, .. }
+            | LedgerError::AfterClose { location, .. }
+            | LedgerError::ClosedWithBalance { location, .. }
+            | LedgerError::BalanceMismatch { location, .. }
+            | LedgerError::PadWithoutBalance { location, .. }
+            | LedgerError::UnknownCommodity { location, .. }
+            | LedgerError::Unbalanced { location, .. }
+            | LedgerError::RateMismatch { location, .. }
+            | LedgerError::MissingRate { location, .. }
+            | LedgerError::MissingCost { location, .. }
+            | LedgerError::NotEnoughUnits { location, .. }
+            | LedgerError::PriceSource { location, .. } => Some(location),
+            LedgerError::Io { .. } | LedgerError::Multiple(_) => None,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            LedgerError::Io { path, message } => format!("Failed to read {}: {}", path, message),
+            LedgerError::Syntax { message, .. } => {
+                format!("Invalid toml: {}", message.trim().replace('\n', ", "))
+            }
+            LedgerError::MissingField { field, .. } => {
+                format!("Missing required field \"{}\"", field)
+            }
+            LedgerError::InvalidValue { field, message, .. } => {
+                format!("Invalid value for \"{}\": {}", field, message)
+            }
+            LedgerError::UnknownAccount { account, .. } => {
+                format!("Account \"{}\" does not exist", account)
+            }
+            LedgerError::DuplicateAccount { account, .. } => {
+                format!("Account \"{}\" is declared more than once", account)
+            }
+            LedgerError::UnknownAccountType { account, .. } => format!(
+                "Account \"{}\" has an unknown type, expected one of Assets, Liabilities, \
+                 Expenses, Income, Equity, Stocks, MutualFunds, Holdings or Cash",
+                account
+            ),
+            LedgerError::BeforeOpen { account, open, .. } => {
+                format!(
+                    "Account \"{}\" is used before it opens on {}",
+                    account, open
+                )
+            }
+            LedgerError::AfterClose { account, close, .. } => {
+                format!(
+                    "Account \"{}\" is used after it closes on {}",
+                    account, close
+                )
+            }
+            LedgerError::ClosedWithBalance {
+                account,
+                close,
+                balance,
+                ..
+            } => format!(
+                "Account \"{}\" closes on {} with a balance of {}",
+                account, close, balance
+            ),
+            LedgerError::BalanceMismatch {
+                account,
+                date,
+                expected,
+                actual,
+                ..
+            } => format!(
+                "Balance of \"{}\" at the end of {} is {}, expected {} (difference {})",
+                account,
+                date,
+                actual,
+                expected,
+                actual - expected,
+            ),
+            LedgerError::PadWithoutBalance { account, .. } => format!(
+                "Pad of account \"{}\" has no balance assertion after it",
+                account
+            ),
+            LedgerError::UnknownCommodity { commodity, .. } => {
+                format!(
+                    "Commodity \"{}\" is not the currency of any account",
+                    commodity
+                )
+            }
+            LedgerError::Unbalanced { difference, .. } => {
+                format!("Transaction does not balance by {}", difference)
+            }
+            LedgerError::RateMismatch {
+                currency,
+                difference,
+                ..
+            } => format!(
+                "Transaction does not balance at its exchange rates by {} {}",
+                difference, currency
+            ),
+            LedgerError::MissingRate { currencies, .. } => format!(
+                "Transaction between {} accounts declares no exchange rate, declare a rate or total",
+                currencies.join(" and ")
+            ),
+            LedgerError::MissingCost { account, .. } => format!(
+                "Cost of the units bought into \"{}\" is unknown, declare a cost and cost_currency",
+                account
+            ),
+            LedgerError::NotEnoughUnits {
+                account,
+                quantity,
+                available,
+                ..
+            } => format!(
+                "Account \"{}\" sells {} units but its lots hold {}",
+                account, quantity, available
+            ),
+            LedgerError::PriceSource {
+                commodity, message, ..
+            } => format!(
+                "Failed to fetch the price of \"{}\": {}",
+                commodity, message
+            ),
+            LedgerError::Multiple(errors) => format!("{} errors found", errors.len()),
+        }
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::Multiple(errors) => {
+                for e in errors {
+                    writeln!(f, "{}\n", e)?;
+                }
+                write!(f, "{}", self.message())
+            }
+            e => {
+                write!(f, "error: {}", e.message())?;
+                if let Some(location) = e.location() {
+                    write!(f, "\n{}", location)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for LedgerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_from_span() {
+        let content = "[[account]]\nname = \"Cash\"\n\n[[transaction]]\ndate = 2023-10-10\n";
+        let start = content.find("[[transaction]]").unwrap();
+        let location = Location::from_span("ledger.toml", content, start..content.len());
+
+        assert_eq!(location.line, 4);
+        assert_eq!(location.column, 1);
+        assert_eq!(location.snippet, "[[transaction]]\ndate = 2023-10-10");
+    }
+
+    #[test]
+    fn test_from_errors() {
+        assert_eq!(LedgerError::from_errors(vec![]), Ok(()));
+
+        let error = LedgerError::MissingField {
+            location: Location::default(),
+            field: "date".to_string(),
+        };
+        let single = LedgerError::from_errors(vec![error.clone()]).unwrap_err();
+        assert_eq!(single, error);
+
+        let multiple = LedgerError::from_errors(vec![error.clone(), error]).unwrap_err();
+        assert_eq!(multiple.errors().len(), 2);
+    }
+}
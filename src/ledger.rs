@@ -1,282 +1,1207 @@
 //! This module defines the main [Ledger] struct and operations.
 
 use crate::accounts::*;
-use crate::price::Price;
-use crate::transaction::Transaction;
+use crate::assertion::{BalanceAssertion, Pad};
+use crate::error::{LedgerError, Location};
+use crate::lots::{Booking, Inventory, Lot, Sale};
+use crate::price::{Price, PriceDb, STALE_PRICE_DAYS};
+use crate::pricesource::{
+    CommandSource, CsvSource, HttpSource, PriceSource, PriceSourceEntry, PriceSourceType,
+};
+use crate::report::{
+    balance_rows, AccountList, AccountListEntry, BalanceReport, BalanceRow, BalanceSection, Cell,
+    JournalEntry, JournalPosting, JournalReport, Report, Row,
+};
+use crate::transaction::{Posting, Transaction};
 use crate::utils::*;
 use chrono::prelude::*;
+use chrono::Months;
 use itertools::Itertools;
-use std::collections::HashMap;
-use std::error::Error;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
-use toml::Value;
+use toml::{Spanned, Value};
 
 #[derive(Debug)]
 pub struct Ledger {
     accounts: Vec<Account>,
     transactions: Vec<Transaction>,
     prices: Vec<Price>,
+    assertions: Vec<BalanceAssertion>,
+    price_sources: Vec<PriceSourceEntry>,
+    options: LedgerOptions,
+}
+
+/// How balances are computed for each period of a balance report.
+///
+/// Income and expenses accounts always show the change within each period,
+/// the mode only applies to balance sheet accounts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BalanceMode {
+    /// Change of the balance within each period.
+    #[default]
+    Change,
+    /// Running balance at the end of each period, since the report start date.
+    Cumulative,
+    /// Running balance at the end of each period, including all prior activity.
+    Historical,
+}
+
+impl FromStr for BalanceMode {
+    type Err = String;
+    fn from_str(input: &str) -> Result<BalanceMode, Self::Err> {
+        match input {
+            "change" => Ok(BalanceMode::Change),
+            "cumulative" => Ok(BalanceMode::Cumulative),
+            "historical" => Ok(BalanceMode::Historical),
+            _ => Err(format!(
+                "Invalid mode {}, expected change, cumulative or historical",
+                input
+            )),
+        }
+    }
+}
+
+/// A posting of the register report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterRow {
+    pub date: NaiveDate,
+    pub account: String,
+    pub payee: String,
+    pub note: String,
+    /// The other accounts of the transaction.
+    pub counter_account: String,
+    pub amount: Decimal,
+    pub currency: String,
+    /// Running balance of the currency across all the registered accounts.
+    pub balance: Decimal,
+}
+
+/// Ledger wide settings, declared in an `[options]` table.
+///
+/// ```toml
+/// [options]
+/// opening_balance_account = "Opening Balances"
+/// max_price_age = 30 # optional
+/// rate_tolerance = 0.01 # optional
+/// record_prices = true  # optional
+/// ```
+#[derive(Debug, Clone)]
+pub struct LedgerOptions {
+    /// Equity account that offsets the opening balances of accounts. Opening
+    /// balances in a currency other than the one of this account, the currency
+    /// of the first opening balance if it is not declared, are offset against
+    /// a `<account>:<currency>` sub-account.
+    pub opening_balance_account: String,
+    /// Maximum age in days of the prices used to value amounts.
+    pub max_price_age: Option<u32>,
+    /// Largest difference allowed between the postings of a transaction
+    /// converted at their exchange rates.
+    pub rate_tolerance: Decimal,
+    /// Adds the exchange rates of the transactions as prices.
+    pub record_prices: bool,
+}
+
+impl Default for LedgerOptions {
+    fn default() -> Self {
+        Self {
+            opening_balance_account: String::from("Opening Balances"),
+            max_price_age: None,
+            rate_tolerance: Decimal::new(5, 3),
+            record_prices: false,
+        }
+    }
+}
+
+/// The entries of a single ledger file, with their position in the file.
+#[derive(Deserialize)]
+struct LedgerFile {
+    #[serde(default)]
+    account: Vec<Spanned<Value>>,
+    #[serde(default)]
+    transaction: Vec<Spanned<Value>>,
+    #[serde(default)]
+    price: Vec<Spanned<Value>>,
+    #[serde(default)]
+    balance: Vec<Spanned<Value>>,
+    #[serde(default)]
+    pad: Vec<Spanned<Value>>,
+    #[serde(default)]
+    price_source: Vec<Spanned<Value>>,
+    options: Option<Spanned<Value>>,
+}
+
+/// A single `[[table]]` entry being parsed. Problems found in its fields
+/// are collected instead of stopping at the first one.
+struct Entry<'a> {
+    value: &'a Value,
+    location: Location,
+    errors: Vec<LedgerError>,
+}
+
+impl<'a> Entry<'a> {
+    fn new(file: &str, content: &str, entry: &'a Spanned<Value>) -> Self {
+        Self {
+            value: entry.get_ref(),
+            location: Location::from_span(file, content, entry.span()),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Parses a field that must be present.
+    fn required<T>(
+        &mut self,
+        field: &str,
+        parse: fn(&Value, &str) -> Result<Option<T>, String>,
+    ) -> Option<T> {
+        let value = self.optional(field, parse);
+        if value.is_none() && self.value.get(field).is_none() {
+            self.errors.push(LedgerError::MissingField {
+                location: self.location.clone(),
+                field: field.to_string(),
+            });
+        }
+        value
+    }
+
+    /// Parses a field that may be omitted.
+    fn optional<T>(
+        &mut self,
+        field: &str,
+        parse: fn(&Value, &str) -> Result<Option<T>, String>,
+    ) -> Option<T> {
+        self.optional_in(self.value, field, parse)
+    }
+
+    /// Parses a field of a table nested in the entry.
+    fn optional_in<T>(
+        &mut self,
+        value: &Value,
+        field: &str,
+        parse: fn(&Value, &str) -> Result<Option<T>, String>,
+    ) -> Option<T> {
+        match parse(value, field) {
+            Ok(v) => v,
+            Err(message) => {
+                self.errors.push(LedgerError::InvalidValue {
+                    location: self.location.clone(),
+                    field: field.to_string(),
+                    message,
+                });
+                None
+            }
+        }
+    }
+
+    /// Returns the parsed value if no problems were found.
+    fn finish<T>(self, parsed: Option<T>, errors: &mut Vec<LedgerError>) -> Option<T> {
+        match self.errors.is_empty() {
+            true => parsed,
+            false => {
+                errors.extend(self.errors);
+                None
+            }
+        }
+    }
+}
+
+/// The currency the balances of a report are priced at, and the commodities
+/// left unconverted because there is no price leading to it.
+#[derive(Debug, Clone, Default)]
+struct Valuation {
+    price: Option<String>,
+    unconverted: Vec<String>,
+}
+
+impl Valuation {
+    /// Currency of the balances of an account in the commodity.
+    fn currency_of(&self, commodity: &str) -> String {
+        match &self.price {
+            Some(p) if !self.unconverted.iter().any(|c| c == commodity) => p.clone(),
+            _ => commodity.to_string(),
+        }
+    }
+
+    /// Report notes on the commodities left unconverted.
+    fn notes(&self) -> Vec<String> {
+        let Some(price) = &self.price else {
+            return Vec::new();
+        };
+        self.unconverted
+            .iter()
+            .map(|c| format!("No price to convert {} into {}, shown in {}", c, price, c))
+            .collect()
+    }
 }
 
 impl Ledger {
-    pub fn new(ledger_file: &str) -> Result<Self, Box<dyn Error>> {
-        let parsed_toml: Value = toml::from_str(&ledger_file)?;
+    /// Parses a ledger from the content of a single toml file.
+    pub fn new(ledger_file: &str) -> Result<Self, LedgerError> {
+        Self::from_sources(&[("<ledger>".to_string(), ledger_file.to_string())])
+    }
+
+    /// Parses a ledger from a list of file paths and their toml content.
+    /// All problems found in all files are reported together.
+    pub fn from_sources(sources: &[(String, String)]) -> Result<Self, LedgerError> {
+        let mut ledger = Self {
+            accounts: Vec::new(),
+            transactions: Vec::new(),
+            prices: Vec::new(),
+            assertions: Vec::new(),
+            price_sources: Vec::new(),
+            options: LedgerOptions::default(),
+        };
+        let mut pads: Vec<Pad> = Vec::new();
+        let mut errors: Vec<LedgerError> = Vec::new();
+
+        for (file, content) in sources {
+            let parsed: LedgerFile = match toml::from_str(content) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    errors.push(LedgerError::Syntax {
+                        location: Location::from_span(file, content, e.span().unwrap_or_default()),
+                        message: e.message().to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            ledger.accounts.extend(Self::_get_accounts(
+                file,
+                content,
+                &parsed.account,
+                &mut errors,
+            ));
+            ledger.transactions.extend(Self::_get_transactions(
+                file,
+                content,
+                &parsed.transaction,
+                &mut errors,
+            ));
+            ledger
+                .prices
+                .extend(Self::_get_prices(file, content, &parsed.price, &mut errors));
+            ledger.assertions.extend(Self::_get_assertions(
+                file,
+                content,
+                &parsed.balance,
+                &mut errors,
+            ));
+            pads.extend(Self::_get_pads(file, content, &parsed.pad, &mut errors));
+            ledger.price_sources.extend(Self::_get_price_sources(
+                file,
+                content,
+                &parsed.price_source,
+                &mut errors,
+            ));
+            if let Some(options) = &parsed.options {
+                ledger._get_options(file, content, options, &mut errors);
+            }
+        }
+        LedgerError::from_errors(errors)?;
+        ledger._count_units();
+        if ledger.options.record_prices {
+            ledger._record_prices();
+        }
+        ledger._open_balances();
+        ledger._apply_pads(pads)?;
+        Ok(ledger)
+    }
 
-        let account_list = parsed_toml.get("account").and_then(|v| v.as_array());
-        let transactions_list = parsed_toml.get("transaction").and_then(|v| v.as_array());
-        let prices_list = parsed_toml.get("price").and_then(|v| v.as_array());
+    /// Parses the ledger options. Options declared in later files override
+    /// the earlier ones.
+    fn _get_options(
+        &mut self,
+        file: &str,
+        content: &str,
+        options: &Spanned<Value>,
+        errors: &mut Vec<LedgerError>,
+    ) {
+        let mut entry = Entry::new(file, content, options);
+        let opening_balance_account = entry.optional("opening_balance_account", parse_value);
+        let max_price_age = entry.optional("max_price_age", parse_value_to_integer);
+        let rate_tolerance = entry.optional("rate_tolerance", parse_value_to_decimal);
+        let record_prices = entry.optional("record_prices", parse_value_to_bool);
 
-        Ok(Self {
-            accounts: Self::_get_accounts(account_list)?,
-            transactions: Self::_get_transactions(transactions_list)?,
-            prices: Self::_get_prices(prices_list)?,
-        })
+        let options = (
+            opening_balance_account,
+            max_price_age,
+            rate_tolerance,
+            record_prices,
+        );
+        if let Some((account, age, tolerance, record)) = entry.finish(Some(options), errors) {
+            if let Some(account) = account {
+                self.options.opening_balance_account = account;
+            }
+            if age.is_some() {
+                self.options.max_price_age = age;
+            }
+            if let Some(tolerance) = tolerance {
+                self.options.rate_tolerance = tolerance;
+            }
+            if let Some(record) = record {
+                self.options.record_prices = record;
+            }
+        }
     }
 
     /// Parses the accounts from the ledger file.
-    fn _get_accounts(account_list: Option<&Vec<Value>>) -> Result<Vec<Account>, String> {
-        let all_accounts: Vec<Account> = match account_list {
-            Some(list) => {
-                let mut accounts = Vec::new();
-
-                for account in list.iter() {
-                    let name = parse_value(account, "name");
-                    let open = parse_value(account, "open");
-                    let currency = parse_value(account, "currency");
-                    let account_type = parse_value(account, "type");
-                    let opening_balance = match account.get("opening_balance") {
-                        Some(f) => f.to_string().parse::<f32>().ok(),
-                        None => None,
-                    };
-                    let account = Account::new(
-                        name.unwrap_or_default(),
-                        open.unwrap_or_default(),
-                        currency.unwrap_or_default(),
-                        account_type.unwrap_or(AccountType::Assets),
-                        opening_balance,
-                    );
+    fn _get_accounts(
+        file: &str,
+        content: &str,
+        account_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<Account> {
+        let mut accounts = Vec::new();
 
-                    accounts.push(account);
+        for account in account_list.iter() {
+            let mut entry = Entry::new(file, content, account);
+            let name = entry.required("name", parse_value::<String>);
+            let open = entry.required("open", parse_value_to_naivedate);
+            let currency = entry.required("currency", parse_value::<String>);
+            let account_type = entry.required("type", parse_value::<AccountType>);
+            let opening_balance = entry.optional("opening_balance", parse_value_to_decimal);
+            let close = entry.optional("close", parse_value_to_naivedate);
+            let booking = entry.optional("booking", parse_value::<Booking>);
+
+            let location = entry.location.clone();
+            let account = match (name, open, currency, account_type) {
+                (Some(name), Some(open), Some(currency), Some(account_type)) => {
+                    let mut a = Account::new(name, open, currency, account_type, opening_balance);
+                    a.close = close;
+                    a.booking = booking.unwrap_or_default();
+                    a.location = Some(location);
+                    Some(a)
                 }
-                accounts
-            }
-            None => Vec::new(),
-        };
-        Ok(all_accounts)
+                _ => None,
+            };
+            accounts.extend(entry.finish(account, errors));
+        }
+        accounts
     }
 
     /// Parses the transactions from the ledger file.
     fn _get_transactions(
-        transactions_list: Option<&Vec<Value>>,
-    ) -> Result<Vec<Transaction>, String> {
-        let all_transactions: Result<Vec<Transaction>, String> = match transactions_list {
-            Some(list) => {
-                let mut transactions = Vec::new();
-
-                for transaction in list.iter() {
-                    let account = parse_value(transaction, "account");
-                    let date = parse_value_to_naivedate(transaction, "date");
-                    let payee = parse_value(transaction, "payee");
-                    let quantity = match transaction.get("quantity") {
-                        Some(q) => q.as_float().map(|f| f as f32),
-                        None => Some(1.0),
-                    };
-                    let amount = parse_value_to_f32::<f32>(transaction, "amount");
-                    let offset_account = parse_value(transaction, "offset_account");
-                    let offset_amount = match transaction.get("offset_amount") {
-                        Some(q) => q.as_float().unwrap_or_default() as f32,
-                        None => amount.unwrap_or_default() * -1.0,
-                    };
-                    let note = parse_value(transaction, "note");
-
-                    let transaction = Transaction::new(
-                        date.unwrap_or_default(),
-                        account.unwrap_or_default(),
-                        payee,
-                        quantity.unwrap_or(1.0),
-                        amount.unwrap_or_default(),
-                        offset_account.unwrap_or_default(),
-                        offset_amount,
-                        note,
-                    );
-                    transactions.push(transaction);
+        file: &str,
+        content: &str,
+        transactions_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<Transaction> {
+        let mut transactions = Vec::new();
+
+        for transaction in transactions_list.iter() {
+            let mut entry = Entry::new(file, content, transaction);
+            let date = entry.required("date", parse_value_to_naivedate);
+            let payee = entry.optional("payee", parse_value);
+            let note = entry.optional("note", parse_value);
+
+            let postings = match entry.value.get("postings") {
+                Some(Value::Array(postings)) => Self::_get_postings(&mut entry, postings),
+                Some(_) => {
+                    entry.errors.push(LedgerError::InvalidValue {
+                        location: entry.location.clone(),
+                        field: "postings".to_string(),
+                        message: "Expected an array of postings".to_string(),
+                    });
+                    None
+                }
+                None => {
+                    let account = entry.required("account", parse_value::<String>);
+                    let quantity = entry
+                        .optional("quantity", parse_value_to_decimal)
+                        .unwrap_or(Decimal::ONE);
+                    let amount = entry.required("amount", parse_value_to_decimal);
+                    let offset_account = entry.required("offset_account", parse_value::<String>);
+                    let offset_amount = entry
+                        .optional("offset_amount", parse_value_to_decimal)
+                        .unwrap_or(-amount.unwrap_or_default() * quantity);
+                    let value = entry.value;
+                    let posting = Self::_get_posting_lot(&mut entry, value);
+                    let rate = Self::_get_posting_rate(&mut entry, value);
+
+                    match (account, amount, offset_account) {
+                        (Some(account), Some(amount), Some(offset_account)) => Some(vec![
+                            rate(posting(Posting::new(account, amount, quantity))),
+                            Posting::new(offset_account, offset_amount, Decimal::ONE),
+                        ]),
+                        _ => None,
+                    }
+                }
+            };
+
+            let location = entry.location.clone();
+            let transaction = match (date, postings) {
+                (Some(date), Some(postings)) => {
+                    let mut t = Transaction::with_postings(date, payee, note, postings);
+                    t.location = Some(location);
+                    Some(t)
                 }
-                Ok(transactions)
+                _ => None,
+            };
+            transactions.extend(entry.finish(transaction, errors));
+        }
+        transactions
+    }
+
+    /// Parses the postings of a split transaction. A single posting without
+    /// an amount receives the amount that balances the transaction.
+    fn _get_postings(entry: &mut Entry, postings_list: &[Value]) -> Option<Vec<Posting>> {
+        let mut postings = Vec::new();
+        let mut elided: Vec<usize> = Vec::new();
+
+        for (i, posting) in postings_list.iter().enumerate() {
+            let account = entry.optional_in(posting, "account", parse_value::<String>);
+            let quantity = entry
+                .optional_in(posting, "quantity", parse_value_to_decimal)
+                .unwrap_or(Decimal::ONE);
+            let amount = entry.optional_in(posting, "amount", parse_value_to_decimal);
+            if amount.is_none() && posting.get("amount").is_none() {
+                elided.push(i);
             }
-            None => Ok(Vec::new()),
-        };
-        all_transactions
+            let lot = Self::_get_posting_lot(entry, posting);
+            let rate = Self::_get_posting_rate(entry, posting);
+            match account {
+                Some(account) => postings.push(rate(lot(Posting::new(
+                    account,
+                    amount.unwrap_or_default(),
+                    quantity,
+                )))),
+                None => entry.errors.push(LedgerError::MissingField {
+                    location: entry.location.clone(),
+                    field: format!("postings[{}].account", i),
+                }),
+            }
+        }
+
+        if elided.len() > 1 {
+            entry.errors.push(LedgerError::InvalidValue {
+                location: entry.location.clone(),
+                field: "postings".to_string(),
+                message: "Only one posting can omit the amount".to_string(),
+            });
+        }
+        if !entry.errors.is_empty() {
+            return None;
+        }
+        if let Some(&i) = elided.first() {
+            let sum: Decimal = postings.iter().map(|p| p.value()).sum();
+            if postings[i].per_unit && !postings[i].quantity.is_zero() {
+                postings[i].amount = -sum / postings[i].quantity;
+            } else {
+                postings[i].amount = -sum;
+                postings[i].quantity = Decimal::ONE;
+            }
+        }
+        Some(postings)
+    }
+
+    /// Parses the optional cost and lot fields of a posting, returned as a
+    /// function that sets them on the posting, along with whether the posting
+    /// declares a quantity of units.
+    fn _get_posting_lot(entry: &mut Entry, posting: &Value) -> impl Fn(Posting) -> Posting {
+        let cost = entry.optional_in(posting, "cost", parse_value_to_decimal);
+        let cost_currency = entry.optional_in(posting, "cost_currency", parse_value::<String>);
+        let lot = entry.optional_in(posting, "lot", parse_value_to_naivedate);
+        let per_unit = posting.get("quantity").is_some();
+        move |mut p| {
+            p.cost = cost;
+            p.cost_currency = cost_currency.clone();
+            p.lot = lot;
+            p.per_unit = per_unit;
+            p
+        }
+    }
+
+    /// Counts the units of the postings to lot accounts that declare a
+    /// quantity: the posting adds the quantity to the balance, and the amount
+    /// is the unit cost or price of the units, unless a cost is declared.
+    fn _count_units(&mut self) {
+        let lot_accounts: HashSet<&str> = self
+            .accounts
+            .iter()
+            .filter(|a| a.account_type.tracks_lots())
+            .map(|a| a.name.as_str())
+            .collect();
+        for p in self
+            .transactions
+            .iter_mut()
+            .flat_map(|t| t.postings.iter_mut())
+            .filter(|p| p.per_unit && lot_accounts.contains(p.account.as_str()))
+        {
+            p.cost = p.cost.or(Some(p.amount));
+            p.amount = p.quantity;
+            p.quantity = Decimal::ONE;
+        }
+    }
+
+    /// Parses the exchange rate of a posting, declared as the `rate` of a unit
+    /// or the `total` of the posting in the currency of the other postings,
+    /// returned as a function that sets it on the posting.
+    fn _get_posting_rate(entry: &mut Entry, posting: &Value) -> impl Fn(Posting) -> Posting {
+        let rate = entry.optional_in(posting, "rate", parse_value_to_decimal);
+        let total = entry.optional_in(posting, "total", parse_value_to_decimal);
+        if rate.is_some() && total.is_some() {
+            entry.errors.push(LedgerError::InvalidValue {
+                location: entry.location.clone(),
+                field: "total".to_string(),
+                message: "Declare either a rate or a total, not both".to_string(),
+            });
+        }
+        move |mut p| {
+            p.rate = match (rate, total) {
+                (Some(rate), _) => Some(rate.abs()),
+                (None, Some(total)) if !p.value().is_zero() => Some((total / p.value()).abs()),
+                _ => None,
+            };
+            p
+        }
     }
 
     /// Parses the commodity prices from the ledger file.
-    fn _get_prices(price_list: Option<&Vec<Value>>) -> Result<Vec<Price>, String> {
-        let all_prices: Result<Vec<Price>, String> = match price_list {
-            Some(list) => {
-                let mut prices = Vec::new();
-
-                for price in list.iter() {
-                    let date = parse_value_to_naivedate(price, "date");
-                    let commodity = parse_value(price, "commodity");
-                    let amount = parse_value_to_f32::<f32>(price, "price");
-                    let currency = parse_value(price, "currency");
-                    let price = Price::new(
-                        date.unwrap_or_default(),
-                        commodity.unwrap_or_default(),
-                        amount.unwrap_or_default(),
-                        currency.unwrap_or_default(),
-                    );
-                    prices.push(price);
+    fn _get_prices(
+        file: &str,
+        content: &str,
+        price_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<Price> {
+        let mut prices = Vec::new();
+
+        for price in price_list.iter() {
+            let mut entry = Entry::new(file, content, price);
+            let date = entry.required("date", parse_value_to_naivedate);
+            let commodity = entry.required("commodity", parse_value::<String>);
+            let amount = entry.required("price", parse_value_to_decimal);
+            let currency = entry.required("currency", parse_value::<String>);
+
+            let location = entry.location.clone();
+            let price = match (date, commodity, amount, currency) {
+                (Some(date), Some(commodity), Some(amount), Some(currency)) => {
+                    let mut p = Price::new(date, commodity, amount, currency);
+                    p.location = Some(location);
+                    Some(p)
+                }
+                _ => None,
+            };
+            prices.extend(entry.finish(price, errors));
+        }
+        prices
+    }
+
+    /// Parses the price sources from the ledger file.
+    fn _get_price_sources(
+        file: &str,
+        content: &str,
+        source_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<PriceSourceEntry> {
+        let mut sources = Vec::new();
+
+        for source in source_list.iter() {
+            let mut entry = Entry::new(file, content, source);
+            let source_type = entry.required("type", parse_value::<PriceSourceType>);
+            let currency = entry.required("currency", parse_value::<String>);
+            let commodities = entry.optional("commodities", parse_value_to_strings);
+            let provider: Option<Box<dyn PriceSource>> = match source_type {
+                Some(PriceSourceType::Http) => {
+                    let url = entry.required("url", parse_value::<String>);
+                    let path = entry.optional("path", parse_value::<String>);
+                    url.map(|url| Box::new(HttpSource::new(url, path)) as Box<dyn PriceSource>)
+                }
+                Some(PriceSourceType::Csv) => {
+                    let file = entry.required("file", parse_value::<String>);
+                    let date_format = entry.optional("date_format", parse_value::<String>);
+                    file.map(|file| {
+                        Box::new(CsvSource { file, date_format }) as Box<dyn PriceSource>
+                    })
+                }
+                Some(PriceSourceType::Command) => {
+                    let command = entry.required("command", parse_value::<String>);
+                    command
+                        .map(|command| Box::new(CommandSource { command }) as Box<dyn PriceSource>)
+                }
+                None => None,
+            };
+
+            let location = entry.location.clone();
+            let source = match (provider, currency) {
+                (Some(source), Some(currency)) => Some(PriceSourceEntry {
+                    source,
+                    currency,
+                    commodities,
+                    location: Some(location),
+                }),
+                _ => None,
+            };
+            sources.extend(entry.finish(source, errors));
+        }
+        sources
+    }
+
+    /// Parses the balance assertions from the ledger file.
+    fn _get_assertions(
+        file: &str,
+        content: &str,
+        balance_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<BalanceAssertion> {
+        let mut assertions = Vec::new();
+
+        for balance in balance_list.iter() {
+            let mut entry = Entry::new(file, content, balance);
+            let date = entry.required("date", parse_value_to_naivedate);
+            let account = entry.required("account", parse_value::<String>);
+            let amount = entry.required("amount", parse_value_to_decimal);
+            let tolerance = entry.optional("tolerance", parse_value_to_decimal);
+
+            let location = entry.location.clone();
+            let assertion = match (date, account, amount) {
+                (Some(date), Some(account), Some(amount)) => {
+                    let mut b =
+                        BalanceAssertion::new(date, account, amount, tolerance.unwrap_or_default());
+                    b.location = Some(location);
+                    Some(b)
+                }
+                _ => None,
+            };
+            assertions.extend(entry.finish(assertion, errors));
+        }
+        assertions
+    }
+
+    /// Parses the pad entries from the ledger file.
+    fn _get_pads(
+        file: &str,
+        content: &str,
+        pad_list: &[Spanned<Value>],
+        errors: &mut Vec<LedgerError>,
+    ) -> Vec<Pad> {
+        let mut pads = Vec::new();
+
+        for pad in pad_list.iter() {
+            let mut entry = Entry::new(file, content, pad);
+            let date = entry.required("date", parse_value_to_naivedate);
+            let account = entry.required("account", parse_value::<String>);
+            let source = entry.required("source", parse_value::<String>);
+
+            let location = entry.location.clone();
+            let pad = match (date, account, source) {
+                (Some(date), Some(account), Some(source)) => {
+                    let mut p = Pad::new(date, account, source);
+                    p.location = Some(location);
+                    Some(p)
                 }
-                Ok(prices)
+                _ => None,
+            };
+            pads.extend(entry.finish(pad, errors));
+        }
+        pads
+    }
+
+    /// Generates a transaction for the opening balance of each account, dated
+    /// at the account opening and offset against the opening balance equity
+    /// account, which is declared if needed in the currency of the first
+    /// opening balance.
+    fn _open_balances(&mut self) {
+        let equity = self.options.opening_balance_account.clone();
+        let mut equity_accounts: Vec<Account> = Vec::new();
+        let equity_currency = self
+            .accounts
+            .iter()
+            .find(|e| e.name == equity)
+            .or_else(|| {
+                self.accounts
+                    .iter()
+                    .find(|a| !a.opening_balance.unwrap_or_default().is_zero())
+            })
+            .map(|a| a.currency.clone());
+
+        for a in &self.accounts {
+            let amount = a.opening_balance.unwrap_or_default();
+            if amount.is_zero() {
+                continue;
             }
-            None => Ok(Vec::new()),
-        };
-        all_prices
+            let offset_account = match equity_currency.as_ref() == Some(&a.currency) {
+                true => equity.clone(),
+                false => format!("{}{}{}", equity, ACCOUNT_SEPARATOR, a.currency),
+            };
+
+            // declare the equity account, open since the earliest opening balance
+            match equity_accounts
+                .iter_mut()
+                .find(|e| e.name == offset_account)
+            {
+                Some(e) => e.open = e.open.min(a.open),
+                None => equity_accounts.push(Account::new(
+                    offset_account.clone(),
+                    a.open,
+                    a.currency.clone(),
+                    AccountType::Equity,
+                    None,
+                )),
+            }
+
+            let mut t = Transaction::new(
+                a.open,
+                a.name.clone(),
+                None,
+                Decimal::ONE,
+                amount,
+                offset_account,
+                -amount,
+                Some("Opening balance".to_string()),
+            );
+            t.location = a.location.clone();
+            t.generated = true;
+            self.transactions.push(t);
+        }
+
+        for e in equity_accounts {
+            if !self.accounts.iter().any(|a| a.name == e.name) {
+                self.accounts.push(e);
+            }
+        }
+    }
+
+    /// Generates the transactions that make the balance assertion following
+    /// each pad hold. Pads are applied in date order, so each one accounts for
+    /// the transactions generated by the previous ones.
+    fn _apply_pads(&mut self, mut pads: Vec<Pad>) -> Result<(), LedgerError> {
+        let mut errors: Vec<LedgerError> = Vec::new();
+        pads.sort_by_key(|p| p.date);
+
+        for pad in pads {
+            let location = pad.location.clone().unwrap_or_default();
+            let assertion = match pad.next_assertion(&self.assertions) {
+                Some(b) => b,
+                None => {
+                    errors.push(LedgerError::PadWithoutBalance {
+                        location,
+                        account: pad.account.clone(),
+                    });
+                    continue;
+                }
+            };
+
+            let difference = assertion.amount - self._balance_at(&pad.account, assertion.date);
+            if difference.is_zero() {
+                continue;
+            }
+            let note = format!("Padding for balance assertion on {}", assertion.date);
+            let mut t = Transaction::new(
+                pad.date,
+                pad.account,
+                None,
+                Decimal::ONE,
+                difference,
+                pad.source,
+                -difference,
+                Some(note),
+            );
+            t.location = Some(location);
+            t.generated = true;
+            self.transactions.push(t);
+        }
+        LedgerError::from_errors(errors)
+    }
+
+    /// Runs all the ledger checks and returns every problem found.
+    pub fn check(&self) -> Result<(), LedgerError> {
+        let errors: Vec<LedgerError> = [
+            self.validate_accounts(),
+            self.validate_transactions(),
+            self.validate_prices(),
+            self.validate_assertions(),
+            self.validate_lots(),
+        ]
+        .into_iter()
+        .filter_map(|r| r.err())
+        .flat_map(|e| e.errors().into_iter().cloned().collect::<Vec<_>>())
+        .collect();
+        LedgerError::from_errors(errors)
+    }
+
+    /// Validates the declared accounts:
+    /// 1. Account names are unique.
+    /// 2. Account types are one of the known types.
+    /// 3. Closed accounts have a zero balance on the closing date.
+    pub fn validate_accounts(&self) -> Result<(), LedgerError> {
+        let mut errors: Vec<LedgerError> = Vec::new();
+
+        for (i, a) in self.accounts.iter().enumerate() {
+            let location = a.location.clone().unwrap_or_default();
+            if self.accounts[..i].iter().any(|b| b.name == a.name) {
+                errors.push(LedgerError::DuplicateAccount {
+                    location: location.clone(),
+                    account: a.name.clone(),
+                });
+            }
+            if a.account_type == AccountType::Unknown {
+                errors.push(LedgerError::UnknownAccountType {
+                    location: location.clone(),
+                    account: a.name.clone(),
+                });
+            }
+            if let Some(close) = a.close {
+                let balance = self._balance_at(&a.name, close);
+                if !balance.is_zero() {
+                    errors.push(LedgerError::ClosedWithBalance {
+                        location,
+                        account: a.name.clone(),
+                        close,
+                        balance,
+                    });
+                }
+            }
+        }
+        LedgerError::from_errors(errors)
     }
 
     /// Validates each transaction in the ledger:
-    /// 1. For each transaction, check if the account is declared.
-    /// 2. For each transaction, check if the amounts are balanced.
-    /// 3. Transactions between accounts with different currencies are not validated for balance.
-    pub fn validate_transactions(&self) {
+    /// 1. For each transaction, check if the accounts of all postings are declared.
+    /// 2. For each transaction, check that no posting is dated outside the account open and
+    ///    close dates.
+    /// 3. For each transaction, check if the amounts are balanced.
+    /// 4. Transactions between accounts with different currencies are not validated for balance.
+    ///
+    /// All the problems found are returned together.
+    pub fn validate_transactions(&self) -> Result<(), LedgerError> {
+        let mut errors: Vec<LedgerError> = Vec::new();
+
         for t in self.transactions.iter() {
-            // check if the account exists
-            let account_exists = self.accounts.iter().any(|a| a.name == t.account);
-            if !account_exists {
-                panic!("Account {} does not exist", t.account)
-            }
-
-            // check if transactions balances
-            let sum_postings = t.amount + t.offset_amount;
-            if sum_postings != 0.0 {
-                // only check check balances if the accounts have the same currency
-                let account_currency = &self.accounts.iter().find(|a| a.name == t.account);
-                let offset_currency = &self.accounts.iter().find(|a| a.name == t.offset_account);
-
-                if let Some(ac) = account_currency {
-                    if let Some(oc) = offset_currency {
-                        if ac.currency.eq(&oc.currency) {
-                            panic!("Transaction does not balance:\n {}", t)
-                        }
+            let location = t.location.clone().unwrap_or_default();
+
+            // check if the accounts exist and are open at the transaction date
+            for p in &t.postings {
+                match self.accounts.iter().find(|a| a.name == p.account) {
+                    Some(a) if t.date < a.open => errors.push(LedgerError::BeforeOpen {
+                        location: location.clone(),
+                        account: a.name.clone(),
+                        open: a.open,
+                    }),
+                    Some(a) if a.is_closed(t.date) => errors.push(LedgerError::AfterClose {
+                        location: location.clone(),
+                        account: a.name.clone(),
+                        close: a.close.unwrap_or_default(),
+                    }),
+                    Some(_) => {}
+                    None => errors.push(LedgerError::UnknownAccount {
+                        location: location.clone(),
+                        account: p.account.clone(),
+                    }),
+                }
+            }
+
+            if let Err(e) = self._check_balance(t) {
+                errors.push(e);
+            }
+        }
+        LedgerError::from_errors(errors)
+    }
+
+    /// Checks that a transaction balances, converting the postings with an
+    /// exchange rate into the currency of the other postings, and returns that
+    /// currency. Transactions in more than one currency need a rate, unless
+    /// they buy or sell units of a lot account, which are valued at their cost
+    /// or price. They are not checked when no cost can be derived.
+    fn _check_balance(&self, transaction: &Transaction) -> Result<Option<String>, LedgerError> {
+        let location = transaction.location.clone().unwrap_or_default();
+        let postings: Vec<(&Posting, Option<&Account>)> = transaction
+            .postings
+            .iter()
+            .map(|p| (p, self.accounts.iter().find(|a| a.name == p.account)))
+            .collect();
+        // currency and value of each posting, none for those with a rate
+        let mut values: Vec<(Option<String>, Decimal)> = postings
+            .iter()
+            .map(|(p, a)| match (p.rate, a) {
+                (None, Some(a)) => (Some(a.currency.clone()), p.value()),
+                (rate, _) => (None, p.value() * rate.unwrap_or(Decimal::ONE)),
+            })
+            .collect();
+        let currencies = |values: &[(Option<String>, Decimal)]| -> Vec<String> {
+            values
+                .iter()
+                .filter_map(|(c, _)| c.clone())
+                .unique()
+                .collect()
+        };
+
+        if currencies(&values).len() > 1
+            && postings
+                .iter()
+                .any(|(_, a)| a.is_some_and(|a| a.account_type.tracks_lots()))
+        {
+            for ((p, a), value) in postings.iter().zip(values.iter_mut()) {
+                let Some(a) = a.filter(|a| a.account_type.tracks_lots()) else {
+                    continue;
+                };
+                match self._unit_price(transaction, p, a) {
+                    Some((cost, currency)) => *value = (Some(currency), p.value() * cost),
+                    None => return Ok(None),
+                }
+            }
+        }
+        let currency = match currencies(&values).as_slice() {
+            [currency] => currency.clone(),
+            [] => return Ok(None),
+            currencies => {
+                return Err(LedgerError::MissingRate {
+                    location,
+                    currencies: currencies.to_vec(),
+                })
+            }
+        };
+
+        let sum_postings: Decimal = values.iter().map(|(_, v)| v).sum();
+        if transaction.postings.iter().all(|p| p.rate.is_none()) {
+            if !sum_postings.is_zero() {
+                return Err(LedgerError::Unbalanced {
+                    location,
+                    difference: sum_postings,
+                });
+            }
+        } else if sum_postings.abs() > self.options.rate_tolerance {
+            return Err(LedgerError::RateMismatch {
+                location,
+                currency,
+                difference: sum_postings,
+            });
+        }
+        Ok(Some(currency))
+    }
+
+    /// Adds the exchange rates of the transactions that balance at them as
+    /// prices of the posting currency, unless a price is declared for the same
+    /// date.
+    fn _record_prices(&mut self) {
+        let mut recorded = Vec::new();
+        for t in &self.transactions {
+            let Ok(Some(currency)) = self._check_balance(t) else {
+                continue;
+            };
+            for p in t.postings.iter().filter(|p| p.rate.is_some()) {
+                let Some(account) = self.accounts.iter().find(|a| a.name == p.account) else {
+                    continue;
+                };
+                let declared = self.prices.iter().chain(recorded.iter()).any(|d: &Price| {
+                    d.date == t.date && d.commodity == account.currency && d.currency == currency
+                });
+                if account.currency == currency || declared {
+                    continue;
+                }
+                let mut price = Price::new(
+                    t.date,
+                    account.currency.clone(),
+                    p.rate.unwrap_or_default(),
+                    currency.clone(),
+                );
+                price.location = t.location.clone();
+                recorded.push(price);
+            }
+        }
+        self.prices.extend(recorded);
+    }
+
+    /// Validates that each balance assertion matches the balance of the account
+    /// at the end of the asserted date.
+    pub fn validate_assertions(&self) -> Result<(), LedgerError> {
+        let mut errors: Vec<LedgerError> = Vec::new();
+
+        for b in &self.assertions {
+            let location = b.location.clone().unwrap_or_default();
+            match self.accounts.iter().find(|a| a.name == b.account) {
+                Some(_) => {
+                    let actual = self._balance_at(&b.account, b.date);
+                    if !b.holds(actual) {
+                        errors.push(LedgerError::BalanceMismatch {
+                            location,
+                            account: b.account.clone(),
+                            date: b.date,
+                            expected: b.amount,
+                            actual,
+                        });
                     }
                 }
+                None => errors.push(LedgerError::UnknownAccount {
+                    location,
+                    account: b.account.clone(),
+                }),
             }
         }
+        LedgerError::from_errors(errors)
+    }
+
+    /// Validates that the cost of every purchase into a lot account is known
+    /// and that sales do not take more units than the lots hold.
+    pub fn validate_lots(&self) -> Result<(), LedgerError> {
+        self.lots(None).map(|_| ())
+    }
+
+    /// Validates that priced commodities are used as the currency of an account.
+    pub fn validate_prices(&self) -> Result<(), LedgerError> {
+        let errors: Vec<LedgerError> = self
+            .prices
+            .iter()
+            .filter(|p| !self.accounts.iter().any(|a| a.currency == p.commodity))
+            .map(|p| LedgerError::UnknownCommodity {
+                location: p.location.clone().unwrap_or_default(),
+                commodity: p.commodity.clone(),
+            })
+            .collect();
+        LedgerError::from_errors(errors)
     }
 
-    /// Print a journal of transactions.
-    pub fn print_journal(
+    /// Journal of the transactions that involve the filtered accounts.
+    pub fn journal_report(
         &mut self,
-        from: Option<String>,
-        to: Option<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
         account_type: Option<String>,
         name: Option<String>,
         payee: Option<String>,
-    ) {
-        self.transactions.sort_by(|a, b| a.date.cmp(&b.date));
-        self.validate_transactions();
-
-        let filtered_transactions: Vec<&Transaction> = match (from, to) {
-            (Some(f), Some(t)) => self._query_by_transaction_date(Some(&f), Some(&t)),
-            (Some(f), None) => self._query_by_transaction_date(Some(&f), None),
-            (None, Some(t)) => self._query_by_transaction_date(None, Some(&t)),
-            (None, None) => self.transactions.iter().collect(),
-        };
+    ) -> Result<JournalReport, LedgerError> {
+        self.transactions.sort_by_key(|t| t.date);
+        self.validate_transactions()?;
+
+        let filtered_transactions = self.transactions_between(from, to);
 
         let filtered_transactions: Vec<&Transaction> = match payee {
-            Some(p) => self._query_by_transaction_payee(&p),
+            Some(p) => self.transactions_by_payee(&p),
             None => filtered_transactions,
         };
 
         let filtered_accounts: Vec<&Account> = match account_type {
-            Some(t) => self._query_by_account_type(&t),
+            Some(t) => self.accounts_by_type(&t),
             None => self.accounts.iter().collect(),
         };
         let filtered_accounts: Vec<&Account> = match name {
-            Some(n) => self._query_by_account_name(&n),
+            Some(n) => self.accounts_by_name(&n),
             None => filtered_accounts,
         };
 
-        let name_list: Vec<usize> = self.accounts.iter().map(|a| a.name.len()).collect();
-        let name_max: &usize = name_list.iter().max().unwrap();
-
+        let mut journal = JournalReport::default();
         for t in &filtered_transactions {
-            let get_account = filtered_accounts
-                .iter()
-                .find(|a| (a.name == t.account) | (a.name == t.offset_account));
+            let get_account = filtered_accounts.iter().find(|a| t.involves(&a.name));
             if get_account.is_some() {
-                let posting = format!(
-                    "{} | {:<name_width$} | {:11.2} | {}",
-                    t.date,
-                    t.account,
-                    t.amount,
-                    t.payee.clone().unwrap_or_default(),
-                    name_width = name_max + 1
-                );
-                let offset = format!(
-                    "{} | {:<name_width$} | {:11.2} |",
-                    t.date,
-                    t.offset_account,
-                    t.offset_amount,
-                    name_width = name_max + 1
-                );
-                println!("{}\n{}", posting, offset);
+                let postings = t
+                    .postings
+                    .iter()
+                    .map(|p| JournalPosting {
+                        account: p.account.clone(),
+                        amount: p.value(),
+                        currency: self
+                            .accounts
+                            .iter()
+                            .find(|a| a.name == p.account)
+                            .map(|a| a.currency.clone()),
+                    })
+                    .collect();
+                journal.entries.push(JournalEntry {
+                    date: t.date,
+                    payee: t.payee.clone(),
+                    note: t.note.clone(),
+                    generated: t.generated,
+                    postings,
+                });
             }
         }
+        Ok(journal)
     }
 
-    /// Print a list of all declared accounts.
-    pub fn print_accounts(self) {
-        let name_list: Vec<usize> = self.accounts.iter().map(|a| a.name.len()).collect();
-        let name_max: &usize = name_list.iter().max().unwrap();
-        for a in self.accounts {
-            let output = format!(
-                "| {} | {} | {:<name_width$} | {}",
-                a.open,
-                a.account_type,
-                a.name.replace('"', ""),
-                a.currency.replace('"', ""),
-                name_width = name_max,
-            );
-            println!("{}", output);
-        }
+    /// List of all declared accounts as a hierarchy, down to `depth` levels.
+    /// Closed accounts can be hidden, and `from` and `to` show only the accounts
+    /// that are open at some point in the period.
+    pub fn accounts_report(
+        &self,
+        depth: Option<usize>,
+        hide_closed: bool,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> AccountList {
+        let today = Local::now().date_naive();
+        let from = from.unwrap_or(NaiveDate::MIN);
+        let to = to.unwrap_or(NaiveDate::MAX);
+        let accounts: Vec<&Account> = self
+            .accounts
+            .iter()
+            .filter(|a| !(hide_closed && a.is_closed(today)))
+            .filter(|a| a.is_active(from, to))
+            .collect();
+
+        let tree = account_tree(accounts.iter().map(|a| a.name.as_str()));
+        let entries = tree
+            .iter()
+            .flat_map(|n| n.flatten(depth))
+            .map(|node| AccountListEntry {
+                name: node.name.clone(),
+                depth: node.depth,
+                account: accounts
+                    .iter()
+                    .find(|a| a.name == node.name)
+                    .map(|a| (*a).clone()),
+            })
+            .collect();
+        AccountList { accounts: entries }
     }
 
-    /// Print a list of account balances.
-    pub fn print_balances(
+    /// Account balances of each period, by account type.
+    #[allow(clippy::too_many_arguments)]
+    pub fn balances_report(
         &mut self,
-        from: Option<String>,
-        to: Option<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
         account_type: Option<Vec<String>>,
         price: Option<String>,
         group: Option<String>,
-    ) {
-        self.validate_transactions();
+        depth: Option<usize>,
+        hide_closed: bool,
+        mode: BalanceMode,
+    ) -> Result<BalanceReport, LedgerError> {
+        self.validate_transactions()?;
 
-        let mut filtered_transactions: Vec<&Transaction> = match (from, to) {
-            (Some(f), Some(t)) => self._query_by_transaction_date(Some(&f), Some(&t)),
-            (Some(f), None) => self._query_by_transaction_date(Some(&f), None),
-            (None, Some(t)) => self._query_by_transaction_date(None, Some(&t)),
-            (None, None) => self.transactions.iter().collect(),
+        // Balances before the report start date, carried by historical balances
+        let opening_balances: HashMap<String, Decimal> = match (mode, from) {
+            (BalanceMode::Historical, Some(start)) => {
+                let history: Vec<&Transaction> = self
+                    .transactions
+                    .iter()
+                    .filter(|t| t.date < start)
+                    .collect();
+                self._get_balances(history)
+            }
+            _ => HashMap::new(),
         };
 
+        let end_date = to.unwrap_or_else(|| Local::now().date_naive());
+
+        let mut filtered_transactions = self.transactions_between(from, to);
+
         // Get all potential account names
         let filtered_accounts: Vec<&Account> = match account_type {
             Some(a) => a
                 .iter()
-                .flat_map(|atype| self._query_by_account_type(atype))
+                .flat_map(|atype| self.accounts_by_type(atype))
                 .collect(),
             None => self.accounts.iter().collect(),
         };
+        let filtered_accounts: Vec<&Account> = filtered_accounts
+            .into_iter()
+            .filter(|a| !(hide_closed && a.is_closed(end_date)))
+            .collect();
 
-        filtered_transactions.sort_by(|a, b| a.date.cmp(&b.date));
+        filtered_transactions.sort_by_key(|t| t.date);
 
-        let balances_by_period =
-            self._group_transactions_by_period(filtered_transactions, price.to_owned(), group);
+        let mut balances_by_period =
+            self._group_transactions_by_period(filtered_transactions, group.to_owned());
+        if mode != BalanceMode::Change {
+            self._accumulate_balances(&mut balances_by_period, opening_balances, &group, from, to);
+        }
+        let valuation = self._value_balances(&mut balances_by_period, &price, &group, end_date);
 
         let sorted_periods: Vec<_> = balances_by_period
             .keys()
-            .sorted_by(|a, b| b.cmp(&a))
+            .sorted_by(|a, b| b.cmp(a))
             .collect();
 
         // Get account names with actual balances
@@ -288,197 +1213,3012 @@ impl Ledger {
         account_names.sort();
         account_names.dedup();
 
-        // find the max lenght of the account names
-        let name_max: Option<usize> = filtered_accounts.iter().map(|a| a.name.len()).max();
-
-        let mut atypes: Vec<_> = filtered_accounts.iter().map(|t| &t.account_type).collect();
-        atypes.dedup();
+        let atypes: Vec<_> = filtered_accounts
+            .iter()
+            .map(|t| &t.account_type)
+            .unique()
+            .collect();
 
-        // Begin printing balances
-        // Print header
-        let header = format!(
-            "{:<name_width$}",
-            "Accounts",
-            name_width = name_max.unwrap_or(15)
-        );
-        print!("\t{:>} ", header);
-        for h in &sorted_periods {
-            print!("\t{:>15}-{}", h.0, h.1);
-        }
-        println!("");
+        let mut report = BalanceReport {
+            periods: sorted_periods
+                .iter()
+                .map(|p| Self::_period_label(p, &group))
+                .collect(),
+            sections: Vec::new(),
+            notes: valuation.notes(),
+        };
 
-        // Print data rows
         for t in atypes {
-            println!("{}", t);
+            let type_accounts: Vec<&Account> = filtered_accounts
+                .iter()
+                .filter(|a| account_names.contains(&a.name) && t.eq(&a.account_type))
+                .copied()
+                .collect();
+            if type_accounts.is_empty() {
+                continue;
+            }
+            report.sections.push(BalanceSection {
+                account_type: t.clone(),
+                rows: Self::_account_rows(
+                    &type_accounts,
+                    &balances_by_period,
+                    &sorted_periods,
+                    &valuation,
+                    depth,
+                    Decimal::ONE,
+                ),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Income statement. Income and expenses are shown with natural signs,
+    /// followed by the totals and the net income (or loss) of each period.
+    pub fn income_statement_report(
+        &mut self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        price: Option<String>,
+        group: Option<String>,
+        depth: Option<usize>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+
+        // Periods are those of the income and expense postings only
+        let reported: Vec<&str> = self
+            .accounts
+            .iter()
+            .filter(|a| matches!(a.account_type, AccountType::Income | AccountType::Expenses))
+            .map(|a| a.name.as_str())
+            .collect();
+        let mut filtered_transactions: Vec<&Transaction> = self
+            .transactions_between(from, to)
+            .into_iter()
+            .filter(|t| {
+                t.postings
+                    .iter()
+                    .any(|p| reported.contains(&p.account.as_str()))
+            })
+            .collect();
+        filtered_transactions.sort_by_key(|t| t.date);
+        let end_date = to.unwrap_or_else(|| Local::now().date_naive());
+
+        let mut balances_by_period =
+            self._group_transactions_by_period(filtered_transactions, group.to_owned());
+        for balances in balances_by_period.values_mut() {
+            balances.retain(|account, _| reported.contains(&account.as_str()));
+        }
+        let valuation = self._value_balances(&mut balances_by_period, &price, &group, end_date);
+        let sorted_periods: Vec<_> = balances_by_period
+            .keys()
+            .sorted_by(|a, b| b.cmp(a))
+            .collect();
+
+        let with_balance =
+            |a: &&Account| balances_by_period.values().any(|b| b.contains_key(&a.name));
+        let income: Vec<&Account> = self
+            .accounts_by_type("Income")
+            .into_iter()
+            .filter(with_balance)
+            .collect();
+        let expenses: Vec<&Account> = self
+            .accounts_by_type("Expenses")
+            .into_iter()
+            .filter(with_balance)
+            .collect();
+
+        let mut report = Report::new(&["Accounts"]);
+        report.columns.extend(
+            sorted_periods
+                .iter()
+                .map(|p| Self::_period_label(p, &group)),
+        );
+
+        let sections = [
+            ("Income", &income, Decimal::NEGATIVE_ONE),
+            ("Expenses", &expenses, Decimal::ONE),
+        ];
+        let mut totals: Vec<Vec<(String, Vec<Decimal>)>> = Vec::new();
+        for (title, accounts, sign) in sections {
+            report.push(Row::section(title));
+            report.extend(balance_rows(&Self::_account_rows(
+                accounts,
+                &balances_by_period,
+                &sorted_periods,
+                &valuation,
+                depth,
+                sign,
+            )));
+            let section_totals = Self::_section_totals(
+                accounts,
+                &balances_by_period,
+                &sorted_periods,
+                &valuation,
+                sign,
+            );
+            report.extend(Self::_total_rows(
+                &format!("Total {}", title),
+                &section_totals,
+            ));
+            totals.push(section_totals);
+        }
+
+        let net = Self::_combine_totals(&totals[0], &totals[1], Decimal::NEGATIVE_ONE);
+        report.push(Row::blank());
+        report.extend(Self::_total_rows("Net Income (Loss)", &net));
+        for note in valuation.notes() {
+            report.note(note);
+        }
+        Ok(report)
+    }
+
+    /// Balance sheet as of the end of the `to` date, today by default. Income
+    /// and expenses are summed into a retained earnings line of the equity
+    /// section, and the report notes whether assets equal liabilities plus
+    /// equity.
+    pub fn balance_sheet_report(
+        &mut self,
+        to: Option<NaiveDate>,
+        price: Option<String>,
+        depth: Option<usize>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+
+        let date = to.unwrap_or_else(|| Local::now().date_naive());
+        let transactions: Vec<&Transaction> = self
+            .transactions
+            .iter()
+            .filter(|t| t.date <= date)
+            .collect();
+        let mut balances = self._get_balances(transactions);
+        balances.retain(|_, value| !value.is_zero());
+        let mut balances_by_period = HashMap::from([((0, 0), balances)]);
+        let valuation = self._value_balances(&mut balances_by_period, &price, &None, date);
+        let periods: Vec<&(u32, u32)> = balances_by_period.keys().collect();
+
+        let with_balance = |a: &&Account| balances_by_period[&(0, 0)].contains_key(&a.name);
+        let of_types = |is_type: fn(&AccountType) -> bool| -> Vec<&Account> {
+            self.accounts
+                .iter()
+                .filter(|a| is_type(&a.account_type))
+                .filter(with_balance)
+                .collect()
+        };
+        let assets = of_types(AccountType::is_asset);
+        let liabilities = of_types(|t| t == &AccountType::Liabilities);
+        let equity = of_types(|t| t == &AccountType::Equity);
+        let earnings = of_types(|t| !t.is_balance_sheet());
+
+        let mut report = Report::new(&["Accounts", &date.to_string()]);
+        let mut totals: Vec<Vec<(String, Vec<Decimal>)>> = Vec::new();
+        let sections = [
+            ("Assets", &assets, Decimal::ONE),
+            ("Liabilities", &liabilities, Decimal::NEGATIVE_ONE),
+            ("Equity", &equity, Decimal::NEGATIVE_ONE),
+        ];
+        for (title, accounts, sign) in sections {
+            report.push(Row::section(title));
+            report.extend(balance_rows(&Self::_account_rows(
+                accounts,
+                &balances_by_period,
+                &periods,
+                &valuation,
+                depth,
+                sign,
+            )));
+            let mut section_totals =
+                Self::_section_totals(accounts, &balances_by_period, &periods, &valuation, sign);
+
+            // Net income of all the periods up to the date
+            if title == "Equity" {
+                let retained = Self::_section_totals(
+                    &earnings,
+                    &balances_by_period,
+                    &periods,
+                    &valuation,
+                    Decimal::NEGATIVE_ONE,
+                );
+                for (i, (currency, values)) in retained.iter().enumerate() {
+                    let name = if i == 0 { "Retained Earnings" } else { "" };
+                    report.push(Self::_values_row(Row::entry, name, values, currency));
+                }
+                section_totals = Self::_combine_totals(&section_totals, &retained, Decimal::ONE);
+            }
+
+            report.extend(Self::_total_rows(
+                &format!("Total {}", title),
+                &section_totals,
+            ));
+            totals.push(section_totals);
+        }
+
+        let liabilities_and_equity = Self::_combine_totals(&totals[1], &totals[2], Decimal::ONE);
+        report.push(Row::blank());
+        report.extend(Self::_total_rows(
+            "Total Liabilities and Equity",
+            &liabilities_and_equity,
+        ));
+
+        // Accounting equation check
+        let differences: Vec<(String, Vec<Decimal>)> =
+            Self::_combine_totals(&totals[0], &liabilities_and_equity, Decimal::NEGATIVE_ONE)
+                .into_iter()
+                .filter(|(_, values)| values.iter().any(|v| !v.is_zero()))
+                .collect();
+        if differences.is_empty() {
+            report.note("Assets = Liabilities + Equity".to_string());
+        }
+        for (currency, values) in differences {
+            report.note(format!(
+                "Assets differ from Liabilities + Equity by {:.2} {}",
+                values[0], currency
+            ));
+        }
+        for note in valuation.notes() {
+            report.note(note);
+        }
+        Ok(report)
+    }
+
+    /// Cash flow statement for the cash accounts, selected by name or by type
+    /// (Cash by default, or Assets when the ledger has no Cash accounts). Cash
+    /// received and paid in the period is grouped by the type and name of the
+    /// account on the other side of each transaction, between the opening and
+    /// closing cash balances. Generated opening balances are part of the
+    /// opening balance.
+    pub fn cashflow_report(
+        &mut self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        account_type: Option<Vec<String>>,
+        name: Option<Vec<String>>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+
+        let cash: Vec<&Account> = match (name, account_type) {
+            (Some(names), _) => self
+                .accounts
+                .iter()
+                .filter(|a| names.iter().any(|n| a.is_under(n)))
+                .collect(),
+            (None, Some(types)) => types
+                .iter()
+                .flat_map(|t| self.accounts_by_type(t))
+                .unique_by(|a| &a.name)
+                .collect(),
+            (None, None) => match self.accounts_by_type("Cash") {
+                cash if cash.is_empty() => self.accounts_by_type("Assets"),
+                cash => cash,
+            },
+        };
+
+        let mut transactions = self.transactions_between(from, to);
+        transactions.sort_by_key(|t| t.date);
+        let (generated, transactions): (Vec<&Transaction>, Vec<&Transaction>) =
+            transactions.into_iter().partition(|t| t.generated);
+        let flows = self._cash_flows(&transactions, &cash);
+
+        // Opening balance of the cash accounts, at the end of the day before the
+        // period, plus the opening balances of the accounts opened in it
+        let opening_date = from.and_then(|f| f.pred_opt());
+        let opening: Vec<(String, Vec<Decimal>)> = cash
+            .iter()
+            .map(|a| &a.currency)
+            .unique()
+            .map(|currency| {
+                let accounts: Vec<&&Account> =
+                    cash.iter().filter(|a| &a.currency == currency).collect();
+                let before: Decimal = match opening_date {
+                    Some(date) => accounts
+                        .iter()
+                        .map(|a| self._balance_at(&a.name, date))
+                        .sum(),
+                    None => Decimal::ZERO,
+                };
+                let opened: Decimal = generated
+                    .iter()
+                    .flat_map(|t| t.postings.iter())
+                    .filter(|p| accounts.iter().any(|a| a.name == p.account))
+                    .map(|p| p.value())
+                    .sum();
+                (currency.clone(), vec![before + opened])
+            })
+            .collect();
+
+        // Received and paid amounts by counterparty type, account and currency
+        let mut by_counterparty: BTreeMap<(String, String, String), (Decimal, Decimal)> =
+            BTreeMap::new();
+        for (account, currency, amount) in flows {
+            let account_type = self
+                .accounts
+                .iter()
+                .find(|a| a.name == account)
+                .map(|a| a.account_type.to_string().trim_end().to_string())
+                .unwrap_or_default();
+            let (inflow, outflow) = by_counterparty
+                .entry((account_type, account, currency))
+                .or_default();
+            match amount.is_sign_positive() {
+                true => *inflow += amount,
+                false => *outflow += amount,
+            }
+        }
+
+        let mut report = Report::new(&["Accounts", "Amount"]);
+        let mut totals: Vec<Vec<(String, Vec<Decimal>)>> = Vec::new();
+        for (title, inflows) in [("Inflows", true), ("Outflows", false)] {
+            report.push(Row::section(title));
+            let rows: Vec<(&String, &String, &String, Decimal)> = by_counterparty
+                .iter()
+                .map(|((t, a, c), (i, o))| (t, a, c, if inflows { *i } else { *o }))
+                .filter(|(_, _, _, amount)| !amount.is_zero())
+                .collect();
+            for (account_type, group) in &rows.iter().group_by(|(t, _, _, _)| *t) {
+                report.push(Row::entry(vec![Cell::from(account_type.as_str())]));
+                for (_, account, currency, amount) in group {
+                    let name = format!("  {}", account);
+                    report.push(Self::_values_row(Row::entry, &name, &[*amount], currency));
+                }
+            }
+            let section_totals: Vec<(String, Vec<Decimal>)> = opening
+                .iter()
+                .map(|(c, _)| {
+                    let total = rows
+                        .iter()
+                        .filter(|(_, _, currency, _)| *currency == c)
+                        .map(|(_, _, _, amount)| *amount)
+                        .sum();
+                    (c.clone(), vec![total])
+                })
+                .collect();
+            totals.push(section_totals);
+        }
+
+        let net = Self::_combine_totals(&totals[0], &totals[1], Decimal::ONE);
+        let closing = Self::_combine_totals(&opening, &net, Decimal::ONE);
+        report.push(Row::blank());
+        let labels = [
+            "Opening Balance",
+            "Total Inflows",
+            "Total Outflows",
+            "Net Change",
+            "Closing Balance",
+        ];
+        for (label, rows) in labels
+            .iter()
+            .zip([&opening, &totals[0], &totals[1], &net, &closing])
+        {
+            report.extend(Self::_total_rows(label, rows));
+        }
+        Ok(report)
+    }
+
+    /// Cash received (positive) and paid (negative) by the `cash` accounts in
+    /// the transactions, as (counterparty account, currency, amount). Each
+    /// posting to a non cash account in the same currency is a counterparty,
+    /// otherwise the whole change of the cash balance is attributed to the first
+    /// posting of the transaction outside the currency.
+    fn _cash_flows(
+        &self,
+        transactions: &[&Transaction],
+        cash: &[&Account],
+    ) -> Vec<(String, String, Decimal)> {
+        let is_cash = |account: &str| cash.iter().any(|a| a.name == account);
+        let currency_of = |account: &str| {
+            self.accounts
+                .iter()
+                .find(|a| a.name == account)
+                .map(|a| a.currency.clone())
+                .unwrap_or_default()
+        };
+
+        let mut flows = Vec::new();
+        for t in transactions {
+            let (cash_postings, others): (Vec<&Posting>, Vec<&Posting>) =
+                t.postings.iter().partition(|p| is_cash(&p.account));
+
+            for currency in cash_postings
+                .iter()
+                .map(|p| currency_of(&p.account))
+                .unique()
+            {
+                let change: Decimal = cash_postings
+                    .iter()
+                    .filter(|p| currency_of(&p.account) == currency)
+                    .map(|p| p.value())
+                    .sum();
+                if change.is_zero() {
+                    continue;
+                }
+
+                let same_currency: Vec<&&Posting> = others
+                    .iter()
+                    .filter(|p| currency_of(&p.account) == currency)
+                    .collect();
+                let same_currency_total: Decimal = same_currency.iter().map(|p| p.value()).sum();
+                if !same_currency.is_empty() && -same_currency_total == change {
+                    flows.extend(
+                        same_currency
+                            .iter()
+                            .map(|p| (p.account.clone(), currency.clone(), -p.value())),
+                    );
+                    continue;
+                }
+
+                let counterparty = others
+                    .iter()
+                    .chain(cash_postings.iter())
+                    .find(|p| currency_of(&p.account) != currency);
+                if let Some(p) = counterparty {
+                    flows.push((p.account.clone(), currency.clone(), change));
+                }
+            }
+        }
+        flows
+    }
+
+    /// Trial balance with the debit and credit totals and the balance of every
+    /// account. The totals of each currency must be zero, the currencies where
+    /// they are not are noted at the end of the report.
+    pub fn trial_balance_report(
+        &mut self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+
+        let transactions = self.transactions_between(from, to);
+        let rows = self._trial_balance(&transactions);
+
+        let mut report = Report::new(&["Accounts", "Debit", "Credit", "Balance"]);
+        for (account, debit, credit) in &rows {
+            report.push(Self::_values_row(
+                Row::entry,
+                &account.name,
+                &[*debit, *credit, debit - credit],
+                &account.currency,
+            ));
+        }
+
+        let totals: Vec<(String, Vec<Decimal>)> = rows
+            .iter()
+            .map(|(a, _, _)| &a.currency)
+            .unique()
+            .map(|currency| {
+                let (debit, credit) = rows
+                    .iter()
+                    .filter(|(a, _, _)| &a.currency == currency)
+                    .fold(
+                        (Decimal::ZERO, Decimal::ZERO),
+                        |(d, c), (_, debit, credit)| (d + debit, c + credit),
+                    );
+                (currency.clone(), vec![debit, credit, debit - credit])
+            })
+            .collect();
+        report.push(Row::blank());
+        report.extend(Self::_total_rows("Total", &totals));
+
+        let unbalanced: Vec<&(String, Vec<Decimal>)> = totals
+            .iter()
+            .filter(|(_, values)| !values[2].is_zero())
+            .collect();
+        if unbalanced.is_empty() {
+            report.note("Debits equal credits in all currencies".to_string());
+        }
+        for (currency, values) in unbalanced {
+            report.note(format!(
+                "Debits and credits in {} differ by {:.2}",
+                currency, values[2]
+            ));
+        }
+        Ok(report)
+    }
+
+    /// Debit and credit totals of every account in the transactions. Credits
+    /// are returned as positive amounts.
+    fn _trial_balance(&self, transactions: &[&Transaction]) -> Vec<(&Account, Decimal, Decimal)> {
+        self.accounts
+            .iter()
+            .map(|a| {
+                let values = transactions
+                    .iter()
+                    .flat_map(|t| t.postings.iter())
+                    .filter(|p| p.account == a.name)
+                    .map(|p| p.value());
+                let (debit, credit) = values.fold((Decimal::ZERO, Decimal::ZERO), |(d, c), v| {
+                    match v.is_sign_positive() {
+                        true => (d + v, c),
+                        false => (d, c - v),
+                    }
+                });
+                (a, debit, credit)
+            })
+            .collect()
+    }
+
+    /// Register of the postings to the accounts, and their subaccounts, with
+    /// the running balance after each posting. The balance starts from the
+    /// balance before the `from` date.
+    pub fn register_report(
+        &mut self,
+        accounts: Vec<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        price: Option<String>,
+    ) -> Result<Report, LedgerError> {
+        self.transactions.sort_by_key(|t| t.date);
+        self.validate_transactions()?;
+
+        let selected: Vec<&Account> = self
+            .accounts
+            .iter()
+            .filter(|a| accounts.iter().any(|n| a.is_under(n)))
+            .collect();
+
+        // Balance brought forward from the transactions before the period
+        let mut opening: Vec<(String, Decimal)> = Vec::new();
+        if let Some(start) = from {
+            let history: Vec<&Transaction> = self
+                .transactions
+                .iter()
+                .filter(|t| t.date < start)
+                .collect();
+            for row in self._register(&history, &selected, &price, HashMap::new()) {
+                match opening.iter_mut().find(|(c, _)| *c == row.currency) {
+                    Some((_, balance)) => *balance = row.balance,
+                    None => opening.push((row.currency, row.balance)),
+                }
+            }
+        }
+
+        let transactions = self.transactions_between(from, to);
+        let rows = self._register(
+            &transactions,
+            &selected,
+            &price,
+            opening.iter().cloned().collect(),
+        );
+
+        let mut report = Report::new(&[
+            "Date",
+            "Account",
+            "Payee",
+            "Note",
+            "Counter Account",
+            "Amount",
+            "Balance",
+        ]);
+        let rows_currencies: Vec<String> = opening
+            .iter()
+            .map(|(c, _)| c.clone())
+            .chain(rows.iter().map(|r| r.currency.clone()))
+            .unique()
+            .collect();
+        for (currency, balance) in &opening {
+            let row = Row::entry(vec![
+                from.map(Cell::from).unwrap_or(Cell::Empty),
+                Cell::Empty,
+                Cell::from("Opening balance"),
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::from(*balance),
+            ]);
+            report.push(row.with_currency(currency));
+        }
+        for r in rows {
+            let row = Row::entry(vec![
+                Cell::from(r.date),
+                Cell::from(r.account),
+                Cell::from(r.payee),
+                Cell::from(r.note),
+                Cell::from(r.counter_account),
+                Cell::from(r.amount),
+                Cell::from(r.balance),
+            ]);
+            report.push(row.with_currency(&r.currency));
+        }
+        if let Some(price) = &price {
+            let unconverted = rows_currencies.iter().filter(|c| *c != price).sorted();
+            for currency in unconverted {
+                report.note(format!(
+                    "No price to convert {} into {}, shown in {}",
+                    currency, price, currency
+                ));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Register rows of the postings to the `accounts` in the transactions,
+    /// converted into the price currency. Running balances start from the
+    /// `opening` balance of each currency.
+    fn _register(
+        &self,
+        transactions: &[&Transaction],
+        accounts: &[&Account],
+        price: &Option<String>,
+        mut opening: HashMap<String, Decimal>,
+    ) -> Vec<RegisterRow> {
+        let prices = self.price_db();
+        let mut rows = Vec::new();
+        for t in transactions {
+            for p in &t.postings {
+                let Some(account) = accounts.iter().find(|a| a.name == p.account) else {
+                    continue;
+                };
+                let (amount, currency) =
+                    Self::_convert(&prices, p.value(), &account.currency, price, t.date);
+                let balance = opening.entry(currency.clone()).or_default();
+                *balance += amount;
+
+                let counter_account = t
+                    .postings
+                    .iter()
+                    .filter(|o| o.account != p.account)
+                    .map(|o| o.account.as_str())
+                    .unique()
+                    .join(", ");
+                let payee = match t.generated {
+                    true => "(generated)".to_string(),
+                    false => t.payee.clone().unwrap_or_default(),
+                };
+                rows.push(RegisterRow {
+                    date: t.date,
+                    account: p.account.clone(),
+                    payee,
+                    note: t.note.clone().unwrap_or_default(),
+                    counter_account,
+                    amount,
+                    currency,
+                    balance: *balance,
+                });
+            }
+        }
+        rows
+    }
+
+    /// Open lots of the accounts under the given paths (all lot accounts if
+    /// None) at the end of the `to` date, with their total quantity and cost.
+    pub fn lots_report(
+        &self,
+        accounts: Option<Vec<String>>,
+        to: Option<NaiveDate>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+        let lots: Vec<Lot> = self
+            .lots(to)?
+            .into_iter()
+            .filter(|l| match &accounts {
+                Some(paths) => paths.iter().any(|p| is_under(&l.account, p)),
+                None => true,
+            })
+            .collect();
 
-            for a in filtered_accounts
+        let mut report = Report::new(&["Acquired", "Quantity", "Unit Cost", "Cost Basis"]);
+        for account in lots.iter().map(|l| &l.account).unique() {
+            let account_lots: Vec<&Lot> = lots.iter().filter(|l| &l.account == account).collect();
+            report.push(Row::section(account));
+            for l in &account_lots {
+                report.push(
+                    Row::entry(vec![
+                        Cell::from(l.date),
+                        Cell::Quantity(l.quantity),
+                        Cell::from(l.cost),
+                        Cell::from(l.cost_basis()),
+                    ])
+                    .with_currency(&l.currency),
+                );
+            }
+            let currencies = account_lots.iter().map(|l| &l.currency).unique();
+            for (i, currency) in currencies.enumerate() {
+                let (quantity, cost_basis) = account_lots
+                    .iter()
+                    .filter(|l| &l.currency == currency)
+                    .fold((Decimal::ZERO, Decimal::ZERO), |(q, c), l| {
+                        (q + l.quantity, c + l.cost_basis())
+                    });
+                let label = if i == 0 { "Total" } else { "" };
+                report.push(
+                    Row::total(vec![
+                        Cell::from(label),
+                        Cell::Quantity(quantity),
+                        Cell::Empty,
+                        Cell::from(cost_basis),
+                    ])
+                    .with_currency(currency),
+                );
+            }
+        }
+        Ok(report)
+    }
+
+    /// Units held in each lot account at the end of the `to` date (today if
+    /// None), with their average cost and market value at the newest price at
+    /// or before that date, and the unrealized gain. Amounts are in the cost
+    /// currency of the lots, or in the `price` currency with the cost converted
+    /// at the prices of the purchase dates.
+    pub fn holdings_report(
+        &self,
+        to: Option<NaiveDate>,
+        accounts: Option<Vec<String>>,
+        price: Option<String>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+        let date = to.unwrap_or_else(|| Local::now().date_naive());
+        let lots: Vec<Lot> = self
+            .lots(Some(date))?
+            .into_iter()
+            .filter(|l| match &accounts {
+                Some(paths) => paths.iter().any(|p| is_under(&l.account, p)),
+                None => true,
+            })
+            .collect();
+
+        let mut report = Report::new(&[
+            "Account",
+            "Commodity",
+            "Quantity",
+            "Avg Cost",
+            "Cost Basis",
+            "Market Value",
+            "Gain",
+            "Gain %",
+        ]);
+        let percent = |gain: Decimal, cost: Decimal| match cost.is_zero() {
+            true => Cell::Empty,
+            false => Cell::Quantity((gain / cost * Decimal::ONE_HUNDRED).round_dp(2)),
+        };
+        let prices = self.price_db();
+        let mut totals: Vec<(String, Decimal, Decimal)> = Vec::new();
+        let holdings = lots
+            .iter()
+            .map(|l| {
+                let currency = price.clone().unwrap_or(l.currency.clone());
+                (&l.account, &l.commodity, currency)
+            })
+            .unique();
+        for (account, commodity, currency) in holdings {
+            let (mut quantity, mut cost) = (Decimal::ZERO, Some(Decimal::ZERO));
+            for l in lots
                 .iter()
-                .filter(|a| account_names.contains(&&a.name) && t.eq(&a.account_type))
+                .filter(|l| &l.account == account && &l.commodity == commodity)
+                .filter(|l| price.is_some() || l.currency == currency)
             {
-                let name = format!(
-                    "{:<name_width$}",
-                    a.name,
-                    name_width = name_max.unwrap_or(15)
+                quantity += l.quantity;
+                cost = cost
+                    .zip(prices.convert(l.cost_basis(), &l.currency, &currency, l.date))
+                    .map(|(c, b)| c + b);
+            }
+            let value = prices.convert(quantity, commodity, &currency, date);
+            let (Some(cost), Some(value)) = (cost, value) else {
+                report.note(format!(
+                    "{} {} in {} left out, no price to convert it into {}",
+                    quantity.normalize(),
+                    commodity,
+                    account,
+                    currency
+                ));
+                continue;
+            };
+            report.push(
+                Row::entry(vec![
+                    Cell::from(account.as_str()),
+                    Cell::from(commodity.as_str()),
+                    Cell::Quantity(quantity),
+                    Cell::from(cost / quantity),
+                    Cell::from(cost),
+                    Cell::from(value),
+                    Cell::from(value - cost),
+                    percent(value - cost, cost),
+                ])
+                .with_currency(&currency),
+            );
+            match totals.iter_mut().find(|t| t.0 == currency) {
+                Some(t) => (t.1, t.2) = (t.1 + cost, t.2 + value),
+                None => totals.push((currency, cost, value)),
+            }
+        }
+
+        for (i, (currency, cost, value)) in totals.iter().enumerate() {
+            let label = if i == 0 { "Total" } else { "" };
+            report.push(
+                Row::total(vec![
+                    Cell::from(label),
+                    Cell::Empty,
+                    Cell::Empty,
+                    Cell::Empty,
+                    Cell::from(*cost),
+                    Cell::from(*value),
+                    Cell::from(value - cost),
+                    percent(value - cost, *cost),
+                ])
+                .with_currency(currency),
+            );
+        }
+        Ok(report)
+    }
+
+    /// Realized gains of the sales between the dates, by tax year of the sale.
+    /// Proceeds are converted into the `price` currency, the cost currency of
+    /// the lot by default, at the price of the sale date and the cost basis at
+    /// the price of the purchase date.
+    pub fn gains_report(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        accounts: Option<Vec<String>>,
+        price: Option<String>,
+    ) -> Result<Report, LedgerError> {
+        self.validate_transactions()?;
+        let sales: Vec<Sale> = self
+            .sales(to)?
+            .into_iter()
+            .filter(|s| from.is_none_or(|f| s.date >= f))
+            .filter(|s| match &accounts {
+                Some(paths) => paths.iter().any(|p| is_under(&s.lot.account, p)),
+                None => true,
+            })
+            .collect();
+
+        let mut report = Report::new(&[
+            "Description",
+            "Account",
+            "Acquired",
+            "Sold",
+            "Proceeds",
+            "Cost Basis",
+            "Gain",
+            "Term",
+        ]);
+        let prices = self.price_db();
+        for year in sales.iter().map(|s| s.date.year()).unique() {
+            report.push(Row::section(&year.to_string()));
+            let mut gains: Vec<(String, bool, Decimal, Decimal)> = Vec::new();
+            for sale in sales.iter().filter(|s| s.date.year() == year) {
+                let lot = &sale.lot;
+                let currency = price.clone().unwrap_or(lot.currency.clone());
+                let proceeds = prices.convert(sale.proceeds, &sale.currency, &currency, sale.date);
+                let cost = prices.convert(lot.cost_basis(), &lot.currency, &currency, lot.date);
+                let description = format!("{} {}", lot.quantity.normalize(), lot.commodity);
+                let (Some(proceeds), Some(cost)) = (proceeds, cost) else {
+                    report.note(format!(
+                        "Sale of {} on {} left out, no price to convert it into {}",
+                        description, sale.date, currency
+                    ));
+                    continue;
+                };
+                let term = if sale.is_long_term() { "Long" } else { "Short" };
+                report.push(
+                    Row::entry(vec![
+                        Cell::from(description),
+                        Cell::from(lot.account.as_str()),
+                        Cell::from(lot.date),
+                        Cell::from(sale.date),
+                        Cell::from(proceeds),
+                        Cell::from(cost),
+                        Cell::from(proceeds - cost),
+                        Cell::from(term),
+                    ])
+                    .with_currency(&currency),
                 );
-                print!("\t{:<15}", name);
-                for p in &sorted_periods {
-                    if let Some(period_data) = balances_by_period.get(p) {
-                        if let Some(value) = period_data.get(&a.name) {
-                            print!(
-                                "\t{:>15.2} {}",
-                                value,
-                                &price.as_ref().unwrap_or(&a.currency)
-                            );
-                        } else {
-                            print!("\t{:>15.2} {}", 0.0, &price.as_ref().unwrap_or(&a.currency));
+                gains.push((currency, sale.is_long_term(), proceeds, cost));
+            }
+
+            for currency in gains.iter().map(|g| g.0.clone()).unique() {
+                for (label, long_term) in [
+                    ("Short-term", Some(false)),
+                    ("Long-term", Some(true)),
+                    ("Total", None),
+                ] {
+                    let (proceeds, cost) = gains
+                        .iter()
+                        .filter(|g| g.0 == currency && long_term.is_none_or(|l| g.1 == l))
+                        .fold((Decimal::ZERO, Decimal::ZERO), |(p, c), g| {
+                            (p + g.2, c + g.3)
+                        });
+                    let mut cells = vec![Cell::from(label), Cell::Empty, Cell::Empty, Cell::Empty];
+                    cells.extend([proceeds, cost, proceeds - cost].map(Cell::from));
+                    report.push(Row::total(cells).with_currency(&currency));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Latest price of each commodity in each currency at the end of the `to`
+    /// date (today if None), one row per commodity and one column per currency,
+    /// or the price history of a single `commodity`. Prices older than
+    /// `max_age` days, the `max_price_age` option by default, are noted as
+    /// stale.
+    pub fn prices_report(
+        &self,
+        commodity: Option<String>,
+        to: Option<NaiveDate>,
+        max_age: Option<u32>,
+    ) -> Result<Report, LedgerError> {
+        let date = to.unwrap_or_else(|| Local::now().date_naive());
+        let max_age = max_age
+            .or(self.options.max_price_age)
+            .unwrap_or(STALE_PRICE_DAYS);
+        let prices = PriceDb::new(&self.prices);
+        let pairs: Vec<(&str, &str)> = prices
+            .pairs()
+            .into_iter()
+            .filter(|(c, cur)| {
+                commodity.as_ref().is_none_or(|x| c == x) && prices.latest(c, cur, date).is_some()
+            })
+            .collect();
+
+        let mut report = match &commodity {
+            Some(_) => {
+                let mut report = Report::new(&["Date", "Commodity", "Price"]);
+                for (c, currency) in &pairs {
+                    for (day, price) in prices.history(c, currency) {
+                        if *day > date {
+                            continue;
+                        }
+                        report.push(
+                            Row::entry(vec![Cell::from(*day), Cell::from(*c), Cell::from(*price)])
+                                .with_currency(currency),
+                        );
+                    }
+                }
+                report
+            }
+            None => {
+                let currencies: Vec<&str> = pairs.iter().map(|p| p.1).unique().sorted().collect();
+                let mut columns = vec!["Commodity"];
+                columns.extend(currencies.iter());
+                let mut report = Report::new(&columns);
+                for c in pairs.iter().map(|p| p.0).unique() {
+                    let mut cells = vec![Cell::from(c)];
+                    cells.extend(currencies.iter().map(|currency| {
+                        match prices.latest(c, currency, date) {
+                            Some((_, price)) => Cell::Quantity(price),
+                            None => Cell::Empty,
                         }
-                    } else {
-                        print!("\t{:>15.2} {}", 0.0, a.currency);
+                    }));
+                    report.push(Row::entry(cells));
+                }
+                report
+            }
+        };
+
+        for (c, currency) in pairs {
+            if let Some((day, _)) = prices.latest(c, currency, date) {
+                let age = (date - day).num_days();
+                if age > max_age as i64 {
+                    report.note(format!(
+                        "{} in {} is stale, last priced on {} ({} days old)",
+                        c, currency, day, age
+                    ));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Prices of the account commodities on the date from the price sources.
+    /// Each commodity is priced in the currency of a source by the first source
+    /// declared for it that has a price. Problems with the sources are returned
+    /// along with the prices found.
+    pub fn fetch_prices(&self, date: NaiveDate) -> (Vec<Price>, Vec<LedgerError>) {
+        let mut prices: Vec<Price> = Vec::new();
+        let mut errors = Vec::new();
+        let commodities: Vec<&str> = self
+            .accounts
+            .iter()
+            .map(|a| a.currency.as_str())
+            .unique()
+            .collect();
+        for commodity in commodities {
+            for s in self.price_sources.iter().filter(|s| s.prices(commodity)) {
+                let priced = |p: &Price| p.commodity == commodity && p.currency == s.currency;
+                if prices.iter().any(priced) {
+                    continue;
+                }
+                match s.source.fetch(commodity, &s.currency, date) {
+                    Ok(price) => prices.extend(price),
+                    Err(message) => errors.push(LedgerError::PriceSource {
+                        location: s.location.clone().unwrap_or_default(),
+                        commodity: commodity.to_string(),
+                        message,
+                    }),
+                }
+            }
+        }
+        (prices, errors)
+    }
+
+    /// Open lots of all the lot accounts at the end of the `to` date (all
+    /// transactions if None), in purchase order within each account.
+    pub fn lots(&self, to: Option<NaiveDate>) -> Result<Vec<Lot>, LedgerError> {
+        self._book_lots(to).map(|(lots, _)| lots)
+    }
+
+    /// Sales of units of the lot accounts up to the `to` date (all transactions
+    /// if None), one per lot sold from, in date order.
+    pub fn sales(&self, to: Option<NaiveDate>) -> Result<Vec<Sale>, LedgerError> {
+        self._book_lots(to).map(|(_, sales)| sales)
+    }
+
+    /// Books the purchases and sales of the lot accounts in date order. Units
+    /// taken without a price in another currency are moved to the accounts
+    /// that receive them in the same transaction.
+    fn _book_lots(&self, to: Option<NaiveDate>) -> Result<(Vec<Lot>, Vec<Sale>), LedgerError> {
+        let mut inventory = Inventory::default();
+        let mut sales: Vec<Sale> = Vec::new();
+        let mut errors: Vec<LedgerError> = Vec::new();
+        let transactions = self
+            .transactions
+            .iter()
+            .filter(|t| to.is_none_or(|d| t.date <= d))
+            .sorted_by_key(|t| t.date);
+
+        for t in transactions {
+            let location = t.location.clone().unwrap_or_default();
+            let postings: Vec<(&Posting, &Account)> = t
+                .postings
+                .iter()
+                .filter_map(|p| {
+                    self.accounts
+                        .iter()
+                        .find(|a| a.name == p.account)
+                        .map(|a| (p, a))
+                })
+                .filter(|(_, a)| a.account_type.tracks_lots())
+                .collect();
+
+            // sales go first, so units moved between accounts keep their lots
+            let mut moved: Vec<Lot> = Vec::new();
+            for (p, a) in postings
+                .iter()
+                .filter(|(p, _)| p.value().is_sign_negative())
+            {
+                let quantity = -p.value();
+                match inventory.reduce(&a.name, quantity, a.booking, p.lot) {
+                    Ok(lots) => match self._unit_price(t, p, a) {
+                        Some((price, currency)) => sales.extend(lots.into_iter().map(|lot| Sale {
+                            date: t.date,
+                            proceeds: lot.quantity * price,
+                            currency: currency.clone(),
+                            lot,
+                        })),
+                        None => moved.extend(lots),
+                    },
+                    Err(available) => errors.push(LedgerError::NotEnoughUnits {
+                        location: location.clone(),
+                        account: a.name.clone(),
+                        quantity,
+                        available,
+                    }),
+                }
+            }
+
+            for (p, a) in postings.iter().filter(|(p, _)| p.value() > Decimal::ZERO) {
+                let lot = |date, quantity, cost, currency| Lot {
+                    account: a.name.clone(),
+                    date,
+                    commodity: a.currency.clone(),
+                    quantity,
+                    cost,
+                    currency,
+                };
+                if let Some((cost, currency)) = self._unit_price(t, p, a) {
+                    inventory.add(lot(t.date, p.value(), cost, currency));
+                    continue;
+                }
+
+                let mut remaining = p.value();
+                for m in moved.iter_mut().filter(|m| m.commodity == a.currency) {
+                    let units = remaining.min(m.quantity);
+                    if !units.is_zero() {
+                        inventory.add(lot(m.date, units, m.cost, m.currency.clone()));
+                    }
+                    m.quantity -= units;
+                    remaining -= units;
+                }
+                match (remaining.is_zero(), t.generated) {
+                    (true, _) => {}
+                    // opening balances have no cost to infer it from
+                    (false, true) => {
+                        inventory.add(lot(t.date, remaining, Decimal::ZERO, a.currency.clone()))
+                    }
+                    (false, false) => errors.push(LedgerError::MissingCost {
+                        location: location.clone(),
+                        account: a.name.clone(),
+                    }),
+                }
+            }
+        }
+        LedgerError::from_errors(errors)?;
+
+        let mut lots = inventory.lots;
+        lots.sort_by(|a, b| a.account.cmp(&b.account));
+        Ok((lots, sales))
+    }
+
+    /// Unit price and its currency of the units a posting buys or sells in a lot
+    /// account, declared or inferred from the postings in other currencies.
+    fn _unit_price(
+        &self,
+        transaction: &Transaction,
+        posting: &Posting,
+        account: &Account,
+    ) -> Option<(Decimal, String)> {
+        let others: Vec<(&Posting, &Account)> = transaction
+            .postings
+            .iter()
+            .filter_map(|p| {
+                self.accounts
+                    .iter()
+                    .find(|a| a.name == p.account)
+                    .map(|a| (p, a))
+            })
+            .filter(|(_, a)| a.currency != account.currency)
+            .collect();
+        let currency = posting
+            .cost_currency
+            .clone()
+            .or_else(|| others.first().map(|(_, a)| a.currency.clone()))?;
+        let cost = match posting.cost {
+            Some(cost) => cost,
+            None => {
+                let total: Decimal = others
+                    .iter()
+                    .filter(|(_, a)| a.currency == currency)
+                    .map(|(p, _)| p.value())
+                    .sum();
+                if total.is_zero() {
+                    return None;
+                }
+                (total / posting.value()).abs()
+            }
+        };
+        Some((cost, currency))
+    }
+
+    /// Rows of the account tree of `accounts` down to `depth` levels, one row
+    /// per node and currency, where each node holds the subtotal of the accounts
+    /// under it. Balances are multiplied by `sign`.
+    fn _account_rows(
+        accounts: &[&Account],
+        balances_by_period: &HashMap<(u32, u32), HashMap<String, Decimal>>,
+        periods: &[&(u32, u32)],
+        valuation: &Valuation,
+        depth: Option<usize>,
+        sign: Decimal,
+    ) -> Vec<BalanceRow> {
+        let mut rows = Vec::new();
+        let tree = account_tree(accounts.iter().map(|a| a.name.as_str()));
+        for node in tree.iter().flat_map(|n| n.flatten(depth)) {
+            let node_accounts: Vec<&Account> = accounts
+                .iter()
+                .filter(|a| a.is_under(&node.name))
+                .copied()
+                .collect();
+            let totals =
+                Self::_section_totals(&node_accounts, balances_by_period, periods, valuation, sign);
+            rows.extend(totals.into_iter().map(|(currency, balances)| BalanceRow {
+                account: node.name.clone(),
+                depth: node.depth,
+                currency,
+                balances,
+            }));
+        }
+        rows
+    }
+
+    /// Total rows, one per currency, with the label on the first one.
+    fn _total_rows(label: &str, totals: &[(String, Vec<Decimal>)]) -> Vec<Row> {
+        totals
+            .iter()
+            .enumerate()
+            .map(|(i, (currency, values))| {
+                let name = if i == 0 { label } else { "" };
+                Self::_values_row(Row::total, name, values, currency)
+            })
+            .collect()
+    }
+
+    /// A report row with a name followed by the value of each period.
+    fn _values_row(
+        kind: fn(Vec<Cell>) -> Row,
+        name: &str,
+        values: &[Decimal],
+        currency: &str,
+    ) -> Row {
+        let mut cells = vec![Cell::from(name)];
+        cells.extend(values.iter().map(|v| Cell::from(*v)));
+        kind(cells).with_currency(currency)
+    }
+
+    /// Column title of a period of `_group_transactions_by_period`.
+    fn _period_label(period: &(u32, u32), group: &Option<String>) -> String {
+        match group.as_deref() {
+            Some("M") => format!("{}-{:02}", period.0, period.1),
+            Some("Q") => format!("{}-Q{}", period.0, period.1),
+            Some("Y") => period.0.to_string(),
+            _ => "Balance".to_string(),
+        }
+    }
+
+    /// Sums the balances of the accounts in each period, per currency.
+    /// Currencies are listed in the order they first appear in `accounts`.
+    fn _section_totals(
+        accounts: &[&Account],
+        balances_by_period: &HashMap<(u32, u32), HashMap<String, Decimal>>,
+        periods: &[&(u32, u32)],
+        valuation: &Valuation,
+        sign: Decimal,
+    ) -> Vec<(String, Vec<Decimal>)> {
+        let currency_of = |a: &Account| valuation.currency_of(&a.currency);
+        accounts
+            .iter()
+            .map(|a| currency_of(a))
+            .unique()
+            .map(|currency| {
+                let values = periods
+                    .iter()
+                    .map(|p| {
+                        let value: Decimal = accounts
+                            .iter()
+                            .filter(|a| currency_of(a) == currency)
+                            .filter_map(|a| balances_by_period.get(p).and_then(|b| b.get(&a.name)))
+                            .sum();
+                        value * sign
+                    })
+                    .collect();
+                (currency, values)
+            })
+            .collect()
+    }
+
+    /// Adds the `other` totals multiplied by `sign` to the `totals`, per currency
+    /// and period.
+    fn _combine_totals(
+        totals: &[(String, Vec<Decimal>)],
+        other: &[(String, Vec<Decimal>)],
+        sign: Decimal,
+    ) -> Vec<(String, Vec<Decimal>)> {
+        let periods = totals
+            .iter()
+            .chain(other.iter())
+            .map(|(_, v)| v.len())
+            .max()
+            .unwrap_or_default();
+        let find = |totals: &[(String, Vec<Decimal>)], currency: &str, i: usize| {
+            totals
+                .iter()
+                .find(|(c, _)| c == currency)
+                .map(|(_, v)| v[i])
+                .unwrap_or_default()
+        };
+        totals
+            .iter()
+            .chain(other.iter())
+            .map(|(c, _)| c.clone())
+            .unique()
+            .map(|c| {
+                let values = (0..periods)
+                    .map(|i| find(totals, &c, i) + sign * find(other, &c, i))
+                    .collect();
+                (c, values)
+            })
+            .collect()
+    }
+
+    /// Balance of an account at the end of the given date.
+    fn _balance_at(&self, account: &str, date: NaiveDate) -> Decimal {
+        self.transactions
+            .iter()
+            .filter(|t| t.date <= date)
+            .flat_map(|t| t.postings.iter())
+            .filter(|p| p.account == account)
+            .map(|p| p.value())
+            .sum()
+    }
+
+    /// Calculates the balance amounts.
+    fn _get_balances(&self, transactions: Vec<&Transaction>) -> HashMap<String, Decimal> {
+        let mut balances: HashMap<String, Decimal> = HashMap::new();
+        for t in &transactions {
+            for p in &t.postings {
+                let amounts = balances.entry(p.account.clone()).or_default();
+                *amounts += p.value();
+            }
+        }
+        balances
+    }
+
+    /// The prices of the ledger, to look up the price of a commodity at a date.
+    pub fn price_db(&self) -> PriceDb {
+        PriceDb::new(&self.prices).with_max_age(self.options.max_price_age)
+    }
+
+    /// Converts the balances of each period into the price currency at the
+    /// prices of the end of the period, or of the `end` date if earlier.
+    /// Commodities without a price in some period are left unconverted in all
+    /// of them, so their balances are not mixed with converted amounts.
+    fn _value_balances(
+        &self,
+        balances_by_period: &mut HashMap<(u32, u32), HashMap<String, Decimal>>,
+        price: &Option<String>,
+        group: &Option<String>,
+        end: NaiveDate,
+    ) -> Valuation {
+        let Some(currency) = price else {
+            return Valuation::default();
+        };
+        let prices = self.price_db();
+        let date_of = |period: &(u32, u32)| Self::_period_end(period, group).min(end);
+
+        let unconverted: Vec<String> = balances_by_period
+            .iter()
+            .flat_map(|(period, balances)| {
+                self.accounts
+                    .iter()
+                    .filter(|a| balances.contains_key(&a.name))
+                    .filter(|a| {
+                        prices
+                            .rate(&a.currency, currency, date_of(period))
+                            .is_none()
+                    })
+                    .map(|a| a.currency.clone())
+            })
+            .unique()
+            .sorted()
+            .collect();
+
+        for (period, balances) in balances_by_period.iter_mut() {
+            for a in &self.accounts {
+                if unconverted.contains(&a.currency) {
+                    continue;
+                }
+                if let Some(balance) = balances.get_mut(&a.name) {
+                    if let Some(value) =
+                        prices.convert(*balance, &a.currency, currency, date_of(period))
+                    {
+                        *balance = value;
                     }
                 }
-                println!("");
             }
         }
+        Valuation {
+            price: price.clone(),
+            unconverted,
+        }
+    }
+
+    /// Period of `_group_transactions_by_period` that holds the date.
+    fn _period_of(date: NaiveDate, group: &Option<String>) -> (u32, u32) {
+        let year = date.year() as u32;
+        match group.as_deref() {
+            Some("M") => (year, date.month()),
+            Some("Q") => (year, quarter(date.month())),
+            Some("Y") => (year, year),
+            _ => (0, 0),
+        }
+    }
+
+    /// Last day of a period of `_group_transactions_by_period`, or the maximum
+    /// date for the single period of ungrouped reports.
+    fn _period_end(period: &(u32, u32), group: &Option<String>) -> NaiveDate {
+        let year = period.0 as i32;
+        let start = match group.as_deref() {
+            Some("M") => NaiveDate::from_ymd_opt(year, period.1, 1).map(|d| (d, 1)),
+            Some("Q") => NaiveDate::from_ymd_opt(year, period.1 * 3 - 2, 1).map(|d| (d, 3)),
+            Some("Y") => NaiveDate::from_ymd_opt(year, 1, 1).map(|d| (d, 12)),
+            _ => None,
+        };
+        start
+            .and_then(|(d, months)| d.checked_add_months(Months::new(months)))
+            .and_then(|d| d.pred_opt())
+            .unwrap_or(NaiveDate::MAX)
+    }
+
+    /// Converts an amount of the commodity into the price currency at the price
+    /// of the date. Amounts of commodities without a price are left unconverted.
+    fn _convert(
+        prices: &PriceDb,
+        amount: Decimal,
+        commodity: &str,
+        price: &Option<String>,
+        date: NaiveDate,
+    ) -> (Decimal, String) {
+        match price {
+            Some(p) => match prices.convert(amount, commodity, p, date) {
+                Some(value) => (value, p.clone()),
+                None => (amount, commodity.to_string()),
+            },
+            None => (amount, commodity.to_string()),
+        }
     }
 
-    /// Calculates the balance amounts.
-    fn _get_balances(
+    /// Turns the balance changes of each period into running balances for the
+    /// balance sheet accounts, starting from the given opening balances. Every
+    /// period from the first to the last one with postings, or from the `from`
+    /// to the `to` date when given, is filled in, so periods without postings
+    /// carry the balances of the period before them.
+    fn _accumulate_balances(
         &self,
-        transactions: Vec<&Transaction>,
-        price: Option<String>,
-    ) -> HashMap<String, f32> {
-        let mut balances: HashMap<String, f32> = HashMap::new();
-        for a in &self.accounts {
-            let opening_balances = balances.entry(a.name.clone()).or_insert(0.0);
-            *opening_balances += a.opening_balance.unwrap_or_default();
-        }
-        for t in &transactions {
-            let amounts = balances.entry(t.account.clone()).or_insert(0.0);
-            *amounts += t.amount * t.quantity;
-            let offsets = balances.entry(t.offset_account.clone()).or_insert(0.0);
-            *offsets += t.offset_amount;
+        balances_by_period: &mut HashMap<(u32, u32), HashMap<String, Decimal>>,
+        mut running: HashMap<String, Decimal>,
+        group: &Option<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) {
+        let first = from
+            .map(|d| Self::_period_of(d, group))
+            .or_else(|| balances_by_period.keys().min().copied());
+        let last = to
+            .map(|d| Self::_period_of(d, group))
+            .or_else(|| balances_by_period.keys().max().copied());
+        if balances_by_period.is_empty() && running.is_empty() {
+            return;
         }
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) => (first, last),
+            (Some(period), None) | (None, Some(period)) => (period, period),
+            (None, None) => ((0, 0), (0, 0)),
+        };
 
-        if let Some(p) = price {
-            let mut relevant_prices: HashMap<String, f32> = HashMap::new();
-            let mut selected_currency: Vec<&Price> =
-                self.prices.iter().filter(|c| c.currency.eq(&p)).collect();
-            selected_currency.sort_by(|a, b| b.date.cmp(&a.date));
-
-            for p in selected_currency {
-                let commodity = p.commodity.clone();
-                let last_price = p.price;
-                relevant_prices.entry(commodity).or_insert(last_price);
-            }
+        let mut periods = vec![first];
+        while let Some(next) = Self::_period_end(&periods[periods.len() - 1], group)
+            .succ_opt()
+            .map(|d| Self::_period_of(d, group))
+            .filter(|p| *p <= last)
+        {
+            periods.push(next);
+        }
 
-            for a in &self.accounts {
-                for (commodity, price) in relevant_prices.iter() {
-                    if commodity.eq(&a.currency) {
-                        let pricings = balances.entry(a.name.clone()).or_insert(0.0);
-                        *pricings *= price;
-                    }
-                }
+        for period in periods {
+            let balances = balances_by_period.entry(period).or_default();
+            for a in self
+                .accounts
+                .iter()
+                .filter(|a| a.account_type.is_balance_sheet())
+            {
+                let total = running.entry(a.name.clone()).or_default();
+                *total += balances.get(&a.name).copied().unwrap_or_default();
+                match total.is_zero() {
+                    true => balances.remove(&a.name),
+                    false => balances.insert(a.name.clone(), *total),
+                };
             }
         }
-        balances
     }
 
     /// Aggregates balances by date grouping.
     fn _group_transactions_by_period(
         &self,
         transactions: Vec<&Transaction>,
-        price: Option<String>,
         group: Option<String>,
-    ) -> HashMap<(u32, u32), HashMap<String, f32>> {
+    ) -> HashMap<(u32, u32), HashMap<String, Decimal>> {
         // Create a HashMap to store data for each period
         let mut transactions_by_period: HashMap<(u32, u32), Vec<&Transaction>> = HashMap::new();
 
         // Iterate through the transactions and categorize data by period
         for entry in transactions {
-            let month = entry.date.month();
-            let quarter = quarter(entry.date.month());
-            let year = entry.date.year() as u32;
-
-            let period: (u32, u32) = match group {
-                Some(ref g) => match g.as_str() {
-                    "M" => (year, month),
-                    "Q" => (year, quarter),
-                    "Y" => (year, year),
-                    _ => (0, 0),
-                },
-                None => (0, 0),
-            };
-
             // Add the entry to the corresponding month in the HashMap
             transactions_by_period
-                .entry(period)
-                .or_insert_with(Vec::new)
+                .entry(Self::_period_of(entry.date, &group))
+                .or_default()
                 .push(entry);
         }
 
-        let mut balances_by_period: HashMap<(u32, u32), HashMap<String, f32>> = HashMap::new();
+        let mut balances_by_period: HashMap<(u32, u32), HashMap<String, Decimal>> = HashMap::new();
 
         // Get balances for each period
         for (period, transactions) in transactions_by_period {
-            let mut bal = self._get_balances(transactions, price.to_owned());
-            bal.retain(|_, &mut value| value != 0.0);
+            let mut bal = self._get_balances(transactions);
+            bal.retain(|_, value| !value.is_zero());
             balances_by_period.entry(period).or_insert(bal);
         }
         balances_by_period
     }
 
-    /// Filter accounts by name.
-    pub fn _query_by_account_name(&self, account_name: &str) -> Vec<&Account> {
-        return self
-            .accounts
+    /// Declared accounts, including the generated opening balance accounts.
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    /// All transactions, including the generated ones.
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Declared prices.
+    pub fn prices(&self) -> &[Price] {
+        &self.prices
+    }
+
+    /// Accounts with the exact given name.
+    pub fn accounts_by_name(&self, account_name: &str) -> Vec<&Account> {
+        self.accounts
             .iter()
             .filter(|a| a.name.as_str().eq(account_name))
-            .collect();
+            .collect()
     }
 
-    /// Filter accounts by class type.
-    pub fn _query_by_account_type(&self, account_type: &str) -> Vec<&Account> {
-        return self
-            .accounts
+    /// Accounts of the given type, like `Assets` or `Expenses`.
+    pub fn accounts_by_type(&self, account_type: &str) -> Vec<&Account> {
+        self.accounts
             .iter()
             .filter(|a| {
                 a.account_type
                     .eq(&AccountType::from_str(account_type).unwrap_or(AccountType::Assets))
             })
-            .collect();
+            .collect()
     }
 
-    /// Filter accounts by currency.
-    pub fn _query_by_account_currency(&self, account_currency: &str) -> Vec<&Account> {
-        return self
-            .accounts
+    /// Accounts held in the given currency.
+    pub fn accounts_by_currency(&self, account_currency: &str) -> Vec<&Account> {
+        self.accounts
             .iter()
             .filter(|a| a.currency.as_str().eq(account_currency))
-            .collect();
+            .collect()
     }
 
-    /// Filter transactions by payee.
-    pub fn _query_by_transaction_payee(&self, payee: &str) -> Vec<&Transaction> {
-        return self
-            .transactions
+    /// Transactions with the exact given payee.
+    pub fn transactions_by_payee(&self, payee: &str) -> Vec<&Transaction> {
+        self.transactions
             .iter()
             .filter(|t| t.payee.eq(&Some(payee.to_string())))
-            .collect();
+            .collect()
     }
 
-    /// Filter transactions by from and to dates.
-    pub fn _query_by_transaction_date(
+    /// Transactions between the `from` and `to` dates, both included. A missing
+    /// date leaves that side of the range open.
+    pub fn transactions_between(
         &self,
-        from: Option<&str>,
-        to: Option<&str>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
     ) -> Vec<&Transaction> {
-        let from = NaiveDate::from_str(from.unwrap_or("1970-01-01")).unwrap_or_default();
-        let to = NaiveDate::from_str(to.unwrap_or("2999-01-01")).unwrap_or_default();
-        let filtered_transactions: Vec<&Transaction> = self
+        self.transactions
+            .iter()
+            .filter(|t| from.is_none_or(|f| t.date >= f) && to.is_none_or(|d| t.date <= d))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::RowKind;
+
+    const ACCOUNTS: &str = r#"
+[[account]]
+open = 2023-01-01
+name = "Savings Account"
+type = "Assets"
+currency = "USD"
+
+[[account]]
+open = 2023-01-01
+name = "Dining"
+type = "Expenses"
+currency = "USD"
+"#;
+
+    /// Ledger of the ACCOUNTS and the given entries.
+    fn with_accounts(entries: &str) -> Result<Ledger, LedgerError> {
+        Ledger::new(&format!("{}{}", ACCOUNTS, entries))
+    }
+
+    #[test]
+    fn test_parse_errors_are_collected() {
+        let error = with_accounts(
+            r#"
+[[transaction]]
+account = "Dining"
+amount = "abc"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap_err();
+        let errors = error.errors();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], LedgerError::MissingField { field, .. } if field == "date"));
+        assert!(matches!(errors[1], LedgerError::InvalidValue { field, .. } if field == "amount"));
+        assert_eq!(errors[0].location().unwrap().line, 14);
+    }
+
+    #[test]
+    fn test_validate_transactions_reports_all_errors() {
+        let ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-10-10
+account = "Dinning"
+amount = 20
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-10-11
+account = "Dining"
+amount = 20
+offset_account = "Savings Account"
+offset_amount = -19.99
+
+[[transaction]]
+date = 2023-11-05
+postings = [
+    { account = "Dining", amount = 30.10 },
+    { account = "Dining", amount = 0.20 },
+    { account = "Savings Account" },
+]
+"#,
+        )
+        .unwrap();
+        let error = ledger.validate_transactions().unwrap_err();
+        let errors = error.errors();
+
+        assert_eq!(errors.len(), 2);
+        assert!(
+            matches!(errors[0], LedgerError::UnknownAccount { account, .. } if account == "Dinning")
+        );
+        assert!(matches!(
+            errors[1],
+            LedgerError::Unbalanced { difference, .. } if *difference == Decimal::from_str("0.01").unwrap()
+        ));
+        assert_eq!(
+            ledger.transactions[2].postings[2].amount,
+            Decimal::from_str("-30.30").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_default_offset_amount() {
+        let ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-10-10
+account = "Dining"
+amount = 20
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-10-11
+account = "Dining"
+quantity = 4
+amount = 12.50
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        assert!(ledger.validate_transactions().is_ok());
+
+        let offsets: Vec<Decimal> = ledger
+            .transactions()
+            .iter()
+            .map(|t| t.postings[1].value())
+            .collect();
+        assert_eq!(offsets, vec![Decimal::from(-20), Decimal::from(-50)]);
+    }
+
+    #[test]
+    fn test_check() {
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Dining"
+type = "Expense"
+currency = "USD"
+
+[[transaction]]
+date = 2022-12-31
+account = "Dining"
+amount = 20
+offset_account = "Savings Account"
+
+[[price]]
+date = 2023-09-30
+commodity = "VOO"
+price = 390.50
+currency = "USD"
+"#,
+        )
+        .unwrap();
+        let error = ledger.check().unwrap_err();
+        let errors = error.errors();
+
+        assert_eq!(errors.len(), 5);
+        assert!(
+            matches!(errors[0], LedgerError::DuplicateAccount { account, .. } if account == "Dining")
+        );
+        assert!(matches!(errors[1], LedgerError::UnknownAccountType { .. }));
+        assert!(
+            matches!(errors[2], LedgerError::BeforeOpen { account, .. } if account == "Dining")
+        );
+        assert!(
+            matches!(errors[3], LedgerError::BeforeOpen { account, .. } if account == "Savings Account")
+        );
+        assert!(
+            matches!(errors[4], LedgerError::UnknownCommodity { commodity, .. } if commodity == "VOO")
+        );
+    }
+
+    #[test]
+    fn test_closed_accounts() {
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+close = 2023-06-30
+name = "Old Savings"
+type = "Assets"
+currency = "USD"
+opening_balance = 50
+
+[[transaction]]
+date = 2023-07-01
+account = "Dining"
+amount = 20
+offset_account = "Old Savings"
+"#,
+        )
+        .unwrap();
+        let error = ledger.check().unwrap_err();
+        let errors = error.errors();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            LedgerError::ClosedWithBalance { balance, .. } if *balance == Decimal::from(50)
+        ));
+        assert!(
+            matches!(errors[1], LedgerError::AfterClose { account, .. } if account == "Old Savings")
+        );
+    }
+
+    #[test]
+    fn test_balance_assertions() {
+        let ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-10-10
+account = "Dining"
+amount = 20
+offset_account = "Savings Account"
+
+[[balance]]
+date = 2023-10-09
+account = "Savings Account"
+amount = 0
+
+[[balance]]
+date = 2023-10-10
+account = "Savings Account"
+amount = -20.00
+
+[[balance]]
+date = 2023-10-31
+account = "Savings Account"
+amount = -25
+"#,
+        )
+        .unwrap();
+        let error = ledger.check().unwrap_err();
+        let errors = error.errors();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            LedgerError::BalanceMismatch { expected, actual, .. }
+                if *expected == Decimal::from(-25) && *actual == Decimal::from(-20)
+        ));
+    }
+
+    #[test]
+    fn test_pads() {
+        let entries = r#"
+[[account]]
+open = 2023-01-01
+name = "Opening Balances"
+type = "Equity"
+currency = "USD"
+
+[[pad]]
+date = 2023-01-01
+account = "Savings Account"
+source = "Opening Balances"
+
+[[transaction]]
+date = 2023-02-01
+account = "Dining"
+amount = 20
+offset_account = "Savings Account"
+
+[[balance]]
+date = 2023-02-28
+account = "Savings Account"
+amount = 480
+"#;
+        let padded = with_accounts(entries).unwrap();
+        let pad = padded.transactions.iter().find(|t| t.generated).unwrap();
+
+        assert_eq!(pad.date, NaiveDate::from_ymd_opt(2023, 1, 1).unwrap());
+        assert_eq!(pad.postings[0].amount, Decimal::from(500));
+        assert_eq!(pad.postings[1].account, "Opening Balances");
+        assert!(padded.check().is_ok());
+
+        let unused_pad = r#"
+[[pad]]
+date = 2023-03-01
+account = "Dining"
+source = "Opening Balances"
+"#;
+        let error = with_accounts(&format!("{}{}", entries, unused_pad)).unwrap_err();
+        assert!(
+            matches!(error, LedgerError::PadWithoutBalance { account, .. } if account == "Dining")
+        );
+    }
+
+    #[test]
+    fn test_opening_balances() {
+        let ledger = r#"
+[options]
+opening_balance_account = "Equity:Opening"
+
+[[account]]
+open = 2023-01-01
+name = "Equity:Opening"
+type = "Equity"
+currency = "USD"
+
+[[account]]
+open = 2023-03-01
+name = "Savings Account"
+type = "Assets"
+currency = "USD"
+opening_balance = 1000.00
+
+[[account]]
+open = 2023-02-01
+name = "Crypto Wallet"
+type = "Assets"
+currency = "BTC"
+opening_balance = 0.56
+"#;
+        let mut ledger = Ledger::new(ledger).unwrap();
+        let opening: Vec<&Transaction> =
+            ledger.transactions.iter().filter(|t| t.generated).collect();
+
+        assert_eq!(opening.len(), 2);
+        assert_eq!(
+            opening[0].date,
+            NaiveDate::from_ymd_opt(2023, 3, 1).unwrap()
+        );
+        assert_eq!(opening[0].postings[1].account, "Equity:Opening");
+        assert_eq!(opening[1].postings[1].account, "Equity:Opening:BTC");
+        assert!(opening.iter().all(|t| t.sum().is_zero()));
+
+        let btc_equity = ledger
+            .accounts
+            .iter()
+            .find(|a| a.name == "Equity:Opening:BTC")
+            .unwrap();
+        assert_eq!(btc_equity.account_type, AccountType::Equity);
+        assert_eq!(btc_equity.currency, "BTC");
+
+        let before_open = ledger
+            .balances_report(
+                None,
+                NaiveDate::from_ymd_opt(2023, 2, 28),
+                Some(vec!["Assets".to_string()]),
+                None,
+                None,
+                None,
+                false,
+                BalanceMode::Change,
+            )
+            .unwrap();
+        assert!(before_open
+            .sections
+            .iter()
+            .flat_map(|s| s.rows.iter())
+            .all(|r| r.account != "Savings Account"));
+        assert!(ledger.check().is_ok());
+
+        // undeclared, the equity account takes the first opening currency
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Crypto Wallet"
+type = "Assets"
+currency = "BTC"
+opening_balance = 0.56
+
+[[account]]
+open = 2023-01-01
+name = "Cash"
+type = "Assets"
+currency = "USD"
+opening_balance = 100.00
+
+[[account]]
+open = 2023-01-01
+name = "Wallet"
+type = "Assets"
+currency = "BTC"
+opening_balance = 0.10
+"#,
+        )
+        .unwrap();
+        let offsets: Vec<&str> = ledger
             .transactions
             .iter()
-            .filter(|t| t.date.ge(&from) && t.date.le(&to))
+            .map(|t| t.postings[1].account.as_str())
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                "Opening Balances",
+                "Opening Balances:USD",
+                "Opening Balances"
+            ]
+        );
+        let equity = ledger.accounts_by_name("Opening Balances");
+        assert_eq!(equity[0].currency, "BTC");
+    }
+
+    #[test]
+    fn test_accumulate_balances() {
+        let mut ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-02-10
+amount = 100.00
+account = "Savings Account"
+offset_account = "Dining"
+
+[[transaction]]
+date = 2023-04-10
+amount = 40.00
+account = "Dining"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let mut savings = |from, to, mode| {
+            let report = ledger
+                .balances_report(
+                    from,
+                    to,
+                    Some(vec!["Assets".to_string()]),
+                    None,
+                    Some("M".to_string()),
+                    None,
+                    false,
+                    mode,
+                )
+                .unwrap();
+            let row = &report.sections[0].rows[0];
+            assert_eq!(row.account, "Savings Account");
+            (report.periods.clone(), row.balances.clone())
+        };
+        let balances =
+            |values: &[i64]| -> Vec<Decimal> { values.iter().map(|v| Decimal::from(*v)).collect() };
+
+        // March has no postings and carries the balance of February
+        let (periods, values) = savings(None, None, BalanceMode::Cumulative);
+        assert_eq!(periods, vec!["2023-04", "2023-03", "2023-02"]);
+        assert_eq!(values, balances(&[60, 100, 100]));
+
+        let (periods, values) = savings(
+            NaiveDate::from_ymd_opt(2023, 3, 1),
+            NaiveDate::from_ymd_opt(2023, 5, 31),
+            BalanceMode::Historical,
+        );
+        assert_eq!(periods, vec!["2023-05", "2023-04", "2023-03"]);
+        assert_eq!(values, balances(&[60, 60, 100]));
+
+        // Without postings in the range, the opening balance is carried
+        let (periods, values) = savings(
+            NaiveDate::from_ymd_opt(2023, 5, 1),
+            None,
+            BalanceMode::Historical,
+        );
+        assert_eq!(periods, vec!["2023-05"]);
+        assert_eq!(values, balances(&[60]));
+    }
+
+    #[test]
+    fn test_income_statement_totals() {
+        let mut ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Salary"
+type = "Income"
+currency = "USD"
+
+[[account]]
+open = 2023-01-01
+name = "Wallet"
+type = "Cash"
+currency = "USD"
+opening_balance = 100.00
+
+[[transaction]]
+date = 2023-02-01
+amount = -500.00
+account = "Salary"
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-02-10
+amount = 120.00
+account = "Dining"
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-03-05
+amount = 40.00
+account = "Savings Account"
+offset_account = "Wallet"
+"#,
+        )
+        .unwrap();
+        let report = ledger
+            .income_statement_report(None, None, None, None, None)
+            .unwrap();
+
+        assert_eq!(report.rows[1].cells[1], Cell::from(Decimal::from(500)));
+        assert_eq!(report.rows[2].cells[0], Cell::from("Total Income"));
+        assert_eq!(report.rows[2].cells[1], Cell::from(Decimal::from(500)));
+        assert_eq!(report.rows[5].cells[1], Cell::from(Decimal::from(120)));
+        assert_eq!(report.rows[7].cells[0], Cell::from("Net Income (Loss)"));
+        assert_eq!(report.rows[7].cells[1], Cell::from(Decimal::from(380)));
+
+        // Months without income or expenses, like the opening balance, get no column
+        let report = ledger
+            .income_statement_report(None, None, None, Some("M".to_string()), None)
+            .unwrap();
+        assert_eq!(report.columns, vec!["Accounts", "2023-02"]);
+    }
+
+    #[test]
+    fn test_balance_sheet() {
+        let mut ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Salary"
+type = "Income"
+currency = "USD"
+
+[[account]]
+open = 2023-01-01
+name = "Credit Card"
+type = "Liabilities"
+currency = "USD"
+
+[[transaction]]
+date = 2023-02-01
+amount = -500.00
+account = "Salary"
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-02-10
+amount = 120.00
+account = "Dining"
+offset_account = "Credit Card"
+"#,
+        )
+        .unwrap();
+        let report = ledger
+            .balance_sheet_report(NaiveDate::from_ymd_opt(2023, 12, 31), None, None)
+            .unwrap();
+        let amount = |label: &str| {
+            report
+                .rows
+                .iter()
+                .find(|r| r.cells.first().map(Cell::text).as_deref() == Some(label))
+                .map(|r| r.cells[1].clone())
+        };
+
+        assert_eq!(report.columns[1], "2023-12-31");
+        assert_eq!(amount("Total Assets"), Some(Cell::from(Decimal::from(500))));
+        assert_eq!(amount("Credit Card"), Some(Cell::from(Decimal::from(120))));
+        assert_eq!(
+            amount("Retained Earnings"),
+            Some(Cell::from(Decimal::from(380)))
+        );
+        assert_eq!(
+            amount("Total Liabilities and Equity"),
+            Some(Cell::from(Decimal::from(500)))
+        );
+        assert_eq!(report.notes, vec!["Assets = Liabilities + Equity"]);
+    }
+
+    #[test]
+    fn test_cash_flows() {
+        let mut ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Groceries"
+type = "Expenses"
+currency = "USD"
+
+[[account]]
+open = 2023-01-01
+name = "Crypto Wallet"
+type = "Assets"
+currency = "BTC"
+
+[[account]]
+open = 2023-01-01
+name = "Wallet"
+type = "Cash"
+currency = "USD"
+
+[[transaction]]
+date = 2023-02-10
+payee = "SUPERMARKET"
+postings = [
+    { account = "Dining", amount = 30.00 },
+    { account = "Groceries", amount = 70.00 },
+    { account = "Savings Account" },
+]
+
+[[transaction]]
+date = 2023-02-12
+account = "Crypto Wallet"
+amount = 0.01
+offset_account = "Savings Account"
+offset_amount = -300.00
+rate = 30000
+
+[[transaction]]
+date = 2023-03-10
+amount = 50.00
+account = "Wallet"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+
+        // Only the Cash accounts by default, not the Assets like the crypto wallet
+        let report = ledger.cashflow_report(None, None, None, None).unwrap();
+        assert_eq!(report.rows[2].cells[0].text(), "  Savings Account");
+        assert_eq!(report.rows[2].cells[1], Cell::from(Decimal::from(50)));
+        assert_eq!(report.rows[9].cells[1], Cell::from(Decimal::from(50)));
+
+        let savings = Some(vec!["Savings Account".to_string()]);
+        let report = ledger.cashflow_report(None, None, None, savings).unwrap();
+        let outflows: Vec<(String, &Cell)> = report.rows[2..9]
+            .iter()
+            .filter(|r| r.currency.is_some())
+            .map(|r| (r.cells[0].text(), &r.cells[1]))
+            .collect();
+        assert_eq!(
+            outflows,
+            vec![
+                (
+                    "  Crypto Wallet".to_string(),
+                    &Cell::from(Decimal::from(-300))
+                ),
+                ("  Wallet".to_string(), &Cell::from(Decimal::from(-50))),
+                ("  Dining".to_string(), &Cell::from(Decimal::from(-30))),
+                ("  Groceries".to_string(), &Cell::from(Decimal::from(-70))),
+            ]
+        );
+        assert_eq!(report.rows[12].cells[1], Cell::from(Decimal::from(-450)));
+
+        // An exchange between cash accounts is a flow of each currency
+        let assets = Some(vec!["Assets".to_string()]);
+        let report = ledger.cashflow_report(None, None, assets, None).unwrap();
+        assert_eq!(report.rows[2].cells[0].text(), "  Savings Account");
+        assert_eq!(report.rows[2].currency.as_deref(), Some("BTC"));
+        assert_eq!(report.rows[2].cells[1], Cell::from(Decimal::new(1, 2)));
+
+        // Without Cash accounts, the Assets accounts and their opening balances
+        let mut ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-02-01
+name = "Checking Account"
+type = "Assets"
+currency = "USD"
+opening_balance = 1000.00
+
+[[transaction]]
+date = 2023-02-10
+amount = 20.00
+account = "Dining"
+offset_account = "Checking Account"
+"#,
+        )
+        .unwrap();
+        let report = ledger.cashflow_report(None, None, None, None).unwrap();
+        let line = |label: &str| {
+            report
+                .rows
+                .iter()
+                .find(|r| r.cells.first() == Some(&Cell::from(label)))
+                .map(|r| r.cells[1].clone())
+        };
+        assert_eq!(line("  Opening Balances"), None);
+        assert_eq!(line("  Dining"), Some(Cell::from(Decimal::from(-20))));
+        assert_eq!(
+            line("Opening Balance"),
+            Some(Cell::from(Decimal::from(1000)))
+        );
+        assert_eq!(
+            line("Closing Balance"),
+            Some(Cell::from(Decimal::from(980)))
+        );
+    }
+
+    #[test]
+    fn test_trial_balance() {
+        let mut ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-02-10
+amount = 100.00
+account = "Savings Account"
+offset_account = "Dining"
+
+[[transaction]]
+date = 2023-03-10
+amount = 40.00
+account = "Dining"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let report = ledger.trial_balance_report(None, None).unwrap();
+        let rows: Vec<&[Cell]> = report
+            .rows
+            .iter()
+            .filter(|r| r.kind == RowKind::Entry)
+            .map(|r| &r.cells[..3])
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                &[
+                    Cell::from("Savings Account"),
+                    Cell::from(Decimal::from(100)),
+                    Cell::from(Decimal::from(40))
+                ],
+                &[
+                    Cell::from("Dining"),
+                    Cell::from(Decimal::from(40)),
+                    Cell::from(Decimal::from(100))
+                ],
+            ]
+        );
+        assert_eq!(report.notes, vec!["Debits equal credits in all currencies"]);
+    }
+
+    #[test]
+    fn test_register() {
+        let mut ledger = with_accounts(
+            r#"
+[[price]]
+date = 2023-01-01
+commodity = "USD"
+currency = "ARS"
+price = 350.00
+
+[[transaction]]
+date = 2023-02-10
+amount = 100.00
+account = "Savings Account"
+offset_account = "Dining"
+payee = "REFUND"
+
+[[transaction]]
+date = 2023-03-10
+amount = 40.00
+account = "Dining"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let savings = vec!["Savings Account".to_string()];
+        let report = ledger
+            .register_report(
+                savings.clone(),
+                NaiveDate::from_ymd_opt(2023, 3, 1),
+                None,
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.rows[0].cells[2], Cell::from("Opening balance"));
+        assert_eq!(report.rows[0].cells[6], Cell::from(Decimal::from(100)));
+        assert_eq!(report.rows[1].cells[4], Cell::from("Dining"));
+        assert_eq!(report.rows[1].cells[5], Cell::from(Decimal::from(-40)));
+        assert_eq!(report.rows[1].cells[6], Cell::from(Decimal::from(60)));
+
+        let report = ledger
+            .register_report(savings, None, None, Some("ARS".to_string()))
+            .unwrap();
+        assert_eq!(report.rows[0].cells[2], Cell::from("REFUND"));
+        assert_eq!(report.rows[1].currency.as_deref(), Some("ARS"));
+        assert_eq!(report.rows[1].cells[6], Cell::from(Decimal::from(21000)));
+    }
+
+    #[test]
+    fn test_journal_report() {
+        let mut ledger = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-03-10
+amount = 40.00
+account = "Dining"
+offset_account = "Savings Account"
+payee = "DINER"
+"#,
+        )
+        .unwrap();
+        let journal = ledger
+            .journal_report(None, None, None, None, Some("DINER".to_string()))
+            .unwrap();
+
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(journal.entries[0].payee.as_deref(), Some("DINER"));
+        assert_eq!(
+            journal.entries[0].postings[1],
+            JournalPosting {
+                account: "Savings Account".to_string(),
+                amount: Decimal::from(-40),
+                currency: Some("USD".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_lots() {
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[account]]
+open = 2023-01-01
+name = "Old Broker"
+type = "Stocks"
+currency = "VOO"
+booking = "LIFO"
+
+[[transaction]]
+date = 2023-03-01
+amount = 10
+account = "Old Broker"
+offset_account = "Savings Account"
+offset_amount = -3450.00
+
+[[transaction]]
+date = 2023-06-01
+payee = "BROKER"
+postings = [
+    { account = "Old Broker", amount = 5 },
+    { account = "Savings Account", amount = -1905.00 },
+    { account = "Dining", amount = 5.00 },
+]
+
+[[transaction]]
+date = 2023-07-01
+amount = 6
+account = "VOO Shares"
+offset_account = "Old Broker"
+"#,
+        )
+        .unwrap();
+        let lots = ledger.lots(None).unwrap();
+
+        assert_eq!(lots.len(), 3);
+        assert_eq!(lots[0].account, "Old Broker");
+        assert_eq!(lots[0].quantity, Decimal::from(9));
+        assert_eq!(lots[0].cost, Decimal::from(345));
+        assert_eq!(lots[1].date, NaiveDate::from_ymd_opt(2023, 6, 1).unwrap());
+        assert_eq!(lots[1].cost, Decimal::from(380));
+        assert_eq!(lots[2].quantity, Decimal::from(1));
+        assert_eq!(lots[2].cost, Decimal::from(345));
+
+        let before = ledger.lots(NaiveDate::from_ymd_opt(2023, 5, 1)).unwrap();
+        assert_eq!(before.len(), 1);
+
+        let errors = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+booking = "LIFO"
+
+[[account]]
+open = 2023-01-01
+name = "Gifts"
+type = "Income"
+currency = "VOO"
+
+[[transaction]]
+date = 2023-03-01
+amount = 10
+account = "VOO Shares"
+offset_account = "Gifts"
+
+[[transaction]]
+date = 2023-04-01
+amount = -2
+account = "VOO Shares"
+offset_account = "Savings Account"
+offset_amount = 800.00
+"#,
+        )
+        .unwrap()
+        .validate_lots()
+        .unwrap_err();
+        let errors = errors.errors();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], LedgerError::MissingCost { .. }));
+        assert!(matches!(
+            errors[1],
+            LedgerError::NotEnoughUnits { available, .. } if available.is_zero()
+        ));
+    }
+
+    #[test]
+    fn test_lots_with_quantity() {
+        let mut ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[transaction]]
+date = 2023-03-01
+quantity = 10
+amount = 390.50
+account = "VOO Shares"
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-06-01
+postings = [
+    { account = "VOO Shares", quantity = 2 },
+    { account = "Savings Account", amount = -800.00 },
+]
+
+[[transaction]]
+date = 2023-10-02
+quantity = -4
+amount = 412.50
+account = "VOO Shares"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let lots = ledger.lots(None).unwrap();
+        assert_eq!(lots.len(), 2);
+        assert_eq!(lots[0].quantity, Decimal::from(6));
+        assert_eq!(lots[0].cost, Decimal::new(39050, 2));
+        assert_eq!(lots[0].currency, "USD");
+        assert_eq!(lots[1].quantity, Decimal::from(2));
+        assert_eq!(lots[1].cost, Decimal::from(400));
+
+        let sales = ledger.sales(None).unwrap();
+        assert_eq!(sales[0].proceeds, Decimal::from(1650));
+        assert_eq!(sales[0].lot.cost_basis(), Decimal::from(1562));
+
+        let balances = ledger
+            .balances_report(
+                None,
+                None,
+                Some(vec!["Assets".to_string(), "Stocks".to_string()]),
+                None,
+                None,
+                None,
+                false,
+                BalanceMode::Cumulative,
+            )
+            .unwrap();
+        let shares = &balances.sections[1].rows[0];
+        assert_eq!(shares.account, "VOO Shares");
+        assert_eq!(shares.currency, "VOO");
+        assert_eq!(shares.balances, vec![Decimal::from(8)]);
+        let savings = &balances.sections[0].rows[0];
+        assert_eq!(savings.balances, vec![Decimal::from(-3055)]);
+    }
+
+    #[test]
+    fn test_lot_transactions_balance_at_cost() {
+        let accounts = r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[transaction]]
+date = 2023-03-01
+amount = 10
+cost = 390.50
+cost_currency = "USD"
+account = "VOO Shares"
+offset_account = "Savings Account"
+"#;
+        let ledger = with_accounts(&format!("{}offset_amount = -3905.00\n", accounts)).unwrap();
+        assert!(ledger.validate_transactions().is_ok());
+
+        let ledger = with_accounts(&format!("{}offset_amount = -100.00\n", accounts)).unwrap();
+        let error = ledger.validate_transactions().unwrap_err();
+        assert!(matches!(
+            error.errors()[0],
+            LedgerError::Unbalanced { difference, .. } if *difference == Decimal::from(3805)
+        ));
+    }
+
+    #[test]
+    fn test_gains() {
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[price]]
+date = 2023-01-01
+commodity = "USD"
+currency = "ARS"
+price = 350.00
+
+[[price]]
+date = 2024-01-01
+commodity = "USD"
+currency = "ARS"
+price = 800.00
+
+[[transaction]]
+date = 2023-03-01
+amount = 10
+account = "VOO Shares"
+offset_account = "Savings Account"
+offset_amount = -3450.00
+
+[[transaction]]
+date = 2024-05-02
+amount = -4
+account = "VOO Shares"
+offset_account = "Savings Account"
+offset_amount = 1800.00
+"#,
+        )
+        .unwrap();
+        let sales = ledger.sales(None).unwrap();
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].proceeds, Decimal::from(1800));
+        assert_eq!(sales[0].lot.cost_basis(), Decimal::from(1380));
+        assert!(sales[0].is_long_term());
+
+        let report = ledger
+            .gains_report(None, None, None, Some("ARS".to_string()))
+            .unwrap();
+        assert_eq!(report.rows[0], Row::section("2024"));
+        assert_eq!(report.rows[1].cells[4], Cell::from(Decimal::from(1440000)));
+        assert_eq!(report.rows[1].cells[5], Cell::from(Decimal::from(483000)));
+        assert_eq!(report.rows[1].cells[7], Cell::from("Long"));
+        assert_eq!(report.rows[4].cells[6], Cell::from(Decimal::from(957000)));
+    }
+
+    #[test]
+    fn test_holdings() {
+        let ledger = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[price]]
+date = 2023-06-30
+commodity = "VOO"
+currency = "USD"
+price = 400.00
+
+[[price]]
+date = 2023-12-31
+commodity = "VOO"
+currency = "USD"
+price = 300.00
+
+[[transaction]]
+date = 2023-03-01
+amount = 10
+account = "VOO Shares"
+offset_account = "Savings Account"
+offset_amount = -3450.00
+
+[[transaction]]
+date = 2023-04-01
+quantity = 5
+amount = 360.00
+account = "VOO Shares"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let report = ledger
+            .holdings_report(NaiveDate::from_ymd_opt(2023, 7, 1), None, None)
+            .unwrap();
+
+        let row = &report.rows[0];
+        assert_eq!(row.cells[2], Cell::Quantity(Decimal::from(15)));
+        assert_eq!(row.cells[3], Cell::from(Decimal::from(350)));
+        assert_eq!(row.cells[5], Cell::from(Decimal::from(6000)));
+        assert_eq!(row.cells[6], Cell::from(Decimal::from(750)));
+        assert_eq!(row.cells[7], Cell::Quantity(Decimal::new(1429, 2)));
+        assert_eq!(row.currency.as_deref(), Some("USD"));
+
+        let report = ledger
+            .holdings_report(NaiveDate::from_ymd_opt(2023, 5, 1), None, None)
+            .unwrap();
+        assert_eq!(report.rows.len(), 0);
+        assert_eq!(report.notes.len(), 1);
+    }
+
+    #[test]
+    fn test_balances_valued_at_period_end() {
+        let mut ledger = with_accounts(
+            r#"
+[[price]]
+date = 2023-01-01
+commodity = "USD"
+currency = "ARS"
+price = 350.00
+
+[[price]]
+date = 2023-03-01
+commodity = "USD"
+currency = "ARS"
+price = 800.00
+
+[[transaction]]
+date = 2023-01-10
+amount = 100.00
+account = "Dining"
+offset_account = "Savings Account"
+
+[[transaction]]
+date = 2023-03-10
+amount = 50.00
+account = "Dining"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let balances = ledger
+            .balances_report(
+                None,
+                NaiveDate::from_ymd_opt(2023, 3, 31),
+                Some(vec!["Assets".to_string()]),
+                Some("ARS".to_string()),
+                Some("M".to_string()),
+                None,
+                false,
+                BalanceMode::Cumulative,
+            )
+            .unwrap();
+
+        assert_eq!(balances.periods, vec!["2023-03", "2023-02", "2023-01"]);
+        let savings = &balances.sections[0].rows[0];
+        assert_eq!(savings.currency, "ARS");
+        assert_eq!(
+            savings.balances,
+            vec![
+                Decimal::from(-120000),
+                Decimal::from(-35000),
+                Decimal::from(-35000)
+            ]
+        );
+
+        // Quarters are valued at the price of their last day
+        let balances = ledger
+            .balances_report(
+                None,
+                None,
+                Some(vec!["Assets".to_string()]),
+                Some("ARS".to_string()),
+                Some("Q".to_string()),
+                None,
+                false,
+                BalanceMode::Change,
+            )
+            .unwrap();
+        assert_eq!(balances.periods, vec!["2023-Q1"]);
+        assert_eq!(
+            balances.sections[0].rows[0].balances,
+            vec![Decimal::from(-120000)]
+        );
+    }
+
+    #[test]
+    fn test_balances_priced_through_other_currencies() {
+        let mut ledger = with_accounts(
+            r#"
+[[price]]
+date = 2023-01-01
+commodity = "EUR"
+currency = "USD"
+price = 1.25
+
+[[price]]
+date = 2023-01-01
+commodity = "EUR"
+currency = "ARS"
+price = 1250.00
+
+[[transaction]]
+date = 2023-01-10
+amount = 100.00
+account = "Dining"
+offset_account = "Savings Account"
+"#,
+        )
+        .unwrap();
+        let mut dining = |price: &str| {
+            ledger
+                .balances_report(
+                    None,
+                    None,
+                    Some(vec!["Expenses".to_string()]),
+                    Some(price.to_string()),
+                    None,
+                    None,
+                    false,
+                    BalanceMode::Change,
+                )
+                .unwrap()
+        };
+
+        let balances = dining("ARS");
+        assert_eq!(balances.sections[0].rows[0].currency, "ARS");
+        assert_eq!(
+            balances.sections[0].rows[0].balances,
+            vec![Decimal::from(100000)]
+        );
+        assert!(balances.notes.is_empty());
+
+        let balances = dining("GBP");
+        assert_eq!(balances.sections[0].rows[0].currency, "USD");
+        assert_eq!(
+            balances.notes,
+            vec!["No price to convert USD into GBP, shown in USD"]
+        );
+    }
+
+    #[test]
+    fn test_prices_report() {
+        let ledger = with_accounts(
+            r#"
+[options]
+max_price_age = 10
+
+[[price]]
+date = 2023-01-01
+commodity = "USD"
+currency = "ARS"
+price = 350.00
+
+[[price]]
+date = 2023-03-01
+commodity = "USD"
+currency = "ARS"
+price = 800.00
+
+[[price]]
+date = 2023-03-20
+commodity = "EUR"
+currency = "USD"
+price = 1.10
+"#,
+        )
+        .unwrap();
+        let report = ledger
+            .prices_report(None, NaiveDate::from_ymd_opt(2023, 3, 25), None)
+            .unwrap();
+
+        assert_eq!(report.columns, vec!["Commodity", "ARS", "USD"]);
+        assert_eq!(
+            report.rows[0].cells[2],
+            Cell::Quantity(Decimal::new(110, 2))
+        );
+        assert_eq!(report.rows[1].cells[1], Cell::Quantity(Decimal::from(800)));
+        assert_eq!(
+            report.notes,
+            vec!["USD in ARS is stale, last priced on 2023-03-01 (24 days old)"]
+        );
+
+        let report = ledger
+            .prices_report(
+                Some("USD".to_string()),
+                NaiveDate::from_ymd_opt(2023, 2, 1),
+                Some(60),
+            )
+            .unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].currency, Some("ARS".to_string()));
+        assert!(report.notes.is_empty());
+    }
+
+    #[test]
+    fn test_fetch_prices() {
+        let url = crate::pricesource::tests::mock_server(r#"{"price": 390.50}"#);
+        let entries = r#"
+[[account]]
+open = 2023-01-01
+name = "VOO Shares"
+type = "Stocks"
+currency = "VOO"
+
+[[price_source]]
+type = "http"
+currency = "USD"
+commodities = ["VOO"]
+url = "URL/quote/{commodity}"
+path = "price"
+
+[[price_source]]
+type = "command"
+currency = "USD"
+command = "echo 1"
+
+[[price_source]]
+type = "command"
+currency = "ARS"
+command = "echo 900"
+
+[[price_source]]
+type = "csv"
+currency = "EUR"
+commodities = ["VOO"]
+file = "missing/{commodity}.csv"
+"#
+        .replace("URL", &url);
+        let ledger = with_accounts(&entries).unwrap();
+        let date = NaiveDate::from_ymd_opt(2023, 10, 2).unwrap();
+        let (prices, errors) = ledger.fetch_prices(date);
+
+        let found: Vec<(&str, &str, Decimal)> = prices
+            .iter()
+            .map(|p| (p.commodity.as_str(), p.currency.as_str(), p.price))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("USD", "ARS", Decimal::from(900)),
+                ("VOO", "USD", Decimal::new(39050, 2)),
+                ("VOO", "ARS", Decimal::from(900)),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(&errors[0], LedgerError::PriceSource { commodity, .. } if commodity == "VOO")
+        );
+
+        let invalid = Ledger::new("[[price_source]]\ntype = \"ftp\"\ncurrency = \"USD\"\n");
+        assert!(matches!(invalid, Err(LedgerError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_exchange_rates() {
+        let ledger = with_accounts(
+            r#"
+[options]
+record_prices = true
+
+[[account]]
+open = 2023-01-01
+name = "Pesos"
+type = "Assets"
+currency = "ARS"
+
+[[transaction]]
+date = 2023-10-02
+amount = -100.00
+account = "Savings Account"
+offset_account = "Pesos"
+offset_amount = 93000.00
+rate = 930.00
+
+[[transaction]]
+date = 2023-10-03
+postings = [
+    { account = "Pesos", amount = 46750.00, total = 50.00 },
+    { account = "Savings Account", amount = -50.00 },
+]
+
+[[transaction]]
+date = 2023-10-04
+amount = -10.00
+account = "Savings Account"
+offset_account = "Pesos"
+offset_amount = 940.00
+rate = 940.00
+"#,
+        )
+        .unwrap();
+        let error = ledger.validate_transactions().unwrap_err();
+        assert!(matches!(
+            error,
+            LedgerError::RateMismatch { ref currency, difference, .. }
+                if currency == "ARS" && difference == Decimal::from(-8460)
+        ));
+
+        let recorded: Vec<(&str, &str, Decimal)> = ledger
+            .prices()
+            .iter()
+            .map(|p| {
+                (
+                    p.commodity.as_str(),
+                    p.currency.as_str(),
+                    p.price.round_dp(6),
+                )
+            })
             .collect();
-        filtered_transactions
+        assert_eq!(recorded[0], ("USD", "ARS", Decimal::from(930)));
+        assert_eq!(recorded[1], ("ARS", "USD", Decimal::new(1070, 6)));
+        assert_eq!(recorded.len(), 2);
+
+        let error = with_accounts(
+            r#"
+[[account]]
+open = 2023-01-01
+name = "Pesos"
+type = "Assets"
+currency = "ARS"
+
+[[transaction]]
+date = 2023-10-02
+amount = -100.00
+account = "Savings Account"
+offset_account = "Pesos"
+offset_amount = 93000.00
+"#,
+        )
+        .unwrap()
+        .validate_transactions()
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            LedgerError::MissingRate { ref currencies, .. } if currencies == &["USD", "ARS"]
+        ));
+
+        let both = with_accounts(
+            r#"
+[[transaction]]
+date = 2023-10-02
+amount = 10.00
+account = "Dining"
+offset_account = "Savings Account"
+rate = 1.00
+total = 10.00
+"#,
+        );
+        assert!(matches!(both, Err(LedgerError::InvalidValue { .. })));
     }
 }
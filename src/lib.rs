@@ -0,0 +1,53 @@
+//! Library API of abacus-rs, a plain text accounting ledger kept in toml files.
+//!
+//! Ledgers are loaded with [read_ledger_files] from a file or a directory of
+//! toml files, or parsed from a string with [Ledger::new]. Reports are
+//! returned as data and rendered with the formatters of the [report] module,
+//! the same way the command line does.
+//!
+//! ```
+//! use abacus_rs::report::{OutputFormat, Report};
+//! use abacus_rs::Ledger;
+//!
+//! let mut ledger = Ledger::new(
+//!     r#"
+//! [[account]]
+//! open = 2023-01-01
+//! name = "Savings Account"
+//! type = "Assets"
+//! currency = "USD"
+//! opening_balance = 1000.00
+//! "#,
+//! )
+//! .unwrap();
+//!
+//! let savings = ledger.accounts_by_name("Savings Account");
+//! assert_eq!(savings[0].currency, "USD");
+//!
+//! let balances = ledger
+//!     .balances_report(None, None, None, None, None, None, false, Default::default())
+//!     .unwrap();
+//! let rows = &balances.sections[0].rows;
+//! assert_eq!(rows[0].balances[0], 1000.into());
+//!
+//! let text = OutputFormat::Text
+//!     .formatter()
+//!     .format(&Report::from(&balances))
+//!     .unwrap();
+//! assert!(text.contains("Savings Account"));
+//! ```
+
+pub mod accounts;
+pub mod assertion;
+pub mod csvimporter;
+pub mod error;
+pub mod ledger;
+pub mod lots;
+pub mod price;
+pub mod pricesource;
+pub mod report;
+pub mod transaction;
+pub mod utils;
+
+pub use ledger::Ledger;
+pub use utils::read_ledger_files;
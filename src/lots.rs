@@ -0,0 +1,273 @@
+//! This module defines the [Lot] struct and the [Booking] methods.
+//!
+//! Units bought into Stocks, MutualFunds and Holdings accounts are kept as
+//! lots, with the date, quantity and unit cost of each purchase. The balance of
+//! these accounts is the number of units held, in the account currency, and the
+//! cost is taken from the postings of the transaction in other currencies.
+//!
+//! ```toml
+//! [[transaction]]
+//! date = 2023-03-01
+//! amount = 10
+//! account = "VOO Shares"
+//! offset_account = "Savings Account"
+//! offset_amount = -3905.00
+//! ```
+//!
+//! Postings that declare a **quantity** buy or sell that number of units, with
+//! the **amount** as the price of each unit, so the purchase above can also be
+//! declared with `quantity = 10` and `amount = 390.50`.
+//!
+//! The unit cost can also be declared with **cost** and **cost_currency**, for
+//! purchases without a posting in the cost currency or transactions buying more
+//! than one commodity. Units moved between accounts in the same currency keep
+//! the date and cost of their lots, and opening balances, which have no cost
+//! to infer, are kept as a lot with a zero cost.
+//!
+//! Sales take units from the lots in the order given by the account booking
+//! method, FIFO (the default), LIFO or AVERAGE, which takes the same share of
+//! every lot so the units sold have the average cost. A specific lot can be
+//! sold with the **lot** field, set to the date of the purchase.
+//! Units taken with a price in another currency are [Sale]s, with the
+//! proceeds the realized gains are computed from.
+//!
+//! ```toml
+//! [[account]]
+//! open = 2023-01-01
+//! name = "VOO Shares"
+//! type = "Stocks"
+//! currency = "VOO"
+//! booking = "LIFO" # optional
+//!
+//! [[transaction]]
+//! date = 2023-10-02
+//! amount = -4
+//! account = "VOO Shares"
+//! offset_account = "Savings Account"
+//! offset_amount = 1650.00
+//! lot = 2023-03-01 # optional
+//! ```
+
+use chrono::{Months, NaiveDate};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// The order in which sales take units from the lots of an account.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Booking {
+    /// Oldest lots first.
+    #[default]
+    Fifo,
+    /// Newest lots first.
+    Lifo,
+    /// The same share of every lot.
+    Average,
+}
+
+impl FromStr for Booking {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Booking, Self::Err> {
+        match input {
+            "FIFO" => Ok(Booking::Fifo),
+            "LIFO" => Ok(Booking::Lifo),
+            "AVERAGE" => Ok(Booking::Average),
+            _ => Err(format!(
+                "Invalid booking {}, expected FIFO, LIFO or AVERAGE",
+                input
+            )),
+        }
+    }
+}
+
+/// Units of a commodity bought on a date at a unit cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot {
+    pub account: String,
+    /// Date of the purchase.
+    pub date: NaiveDate,
+    pub commodity: String,
+    pub quantity: Decimal,
+    /// Cost of each unit.
+    pub cost: Decimal,
+    /// Currency of the cost.
+    pub currency: String,
+}
+
+impl Lot {
+    /// Total cost of the units of the lot.
+    pub fn cost_basis(&self) -> Decimal {
+        self.quantity * self.cost
+    }
+}
+
+/// Units of a lot sold, with their share of the proceeds of the sale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sale {
+    /// Date of the sale.
+    pub date: NaiveDate,
+    /// The units of the lot sold, with their purchase date and cost.
+    pub lot: Lot,
+    pub proceeds: Decimal,
+    /// Currency of the proceeds.
+    pub currency: String,
+}
+
+impl Sale {
+    /// Returns true if the units were held for more than a year.
+    pub fn is_long_term(&self) -> bool {
+        self.lot
+            .date
+            .checked_add_months(Months::new(12))
+            .is_some_and(|d| self.date > d)
+    }
+}
+
+/// The open lots of all the accounts, in purchase order.
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    pub lots: Vec<Lot>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, lot: Lot) {
+        self.lots.push(lot);
+    }
+
+    /// Takes `quantity` units from the lots of the account, all of them from
+    /// the lots bought on the `lot` date if given. Returns the parts of the
+    /// lots taken, or the units available if there are not enough.
+    pub fn reduce(
+        &mut self,
+        account: &str,
+        quantity: Decimal,
+        booking: Booking,
+        lot: Option<NaiveDate>,
+    ) -> Result<Vec<Lot>, Decimal> {
+        let mut indices: Vec<usize> = self
+            .lots
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.account == account && lot.is_none_or(|d| l.date == d))
+            .map(|(i, _)| i)
+            .collect();
+        let available: Decimal = indices.iter().map(|&i| self.lots[i].quantity).sum();
+        if quantity > available {
+            return Err(available);
+        }
+
+        let mut remaining = quantity;
+        let mut taken: Vec<(usize, Decimal)> = Vec::new();
+        match (booking, lot) {
+            (Booking::Average, None) => {
+                for (n, &i) in indices.iter().enumerate() {
+                    let units = match n + 1 == indices.len() {
+                        true => remaining,
+                        false => self.lots[i].quantity * quantity / available,
+                    };
+                    remaining -= units;
+                    taken.push((i, units));
+                }
+            }
+            _ => {
+                if booking == Booking::Lifo && lot.is_none() {
+                    indices.reverse();
+                }
+                for i in indices {
+                    let units = remaining.min(self.lots[i].quantity);
+                    remaining -= units;
+                    taken.push((i, units));
+                }
+            }
+        }
+
+        let mut lots = Vec::new();
+        for (i, units) in taken.into_iter().filter(|(_, u)| !u.is_zero()) {
+            self.lots[i].quantity -= units;
+            lots.push(Lot {
+                quantity: units,
+                ..self.lots[i].clone()
+            });
+        }
+        self.lots.retain(|l| !l.quantity.is_zero());
+        Ok(lots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> Inventory {
+        let lot = |day, quantity, cost| Lot {
+            account: "VOO Shares".to_string(),
+            date: NaiveDate::from_ymd_opt(2023, 3, day).unwrap(),
+            commodity: "VOO".to_string(),
+            quantity: Decimal::from(quantity),
+            cost: Decimal::from(cost),
+            currency: "USD".to_string(),
+        };
+        Inventory {
+            lots: vec![lot(1, 10, 300), lot(2, 10, 400)],
+        }
+    }
+
+    #[test]
+    fn test_reduce_fifo_and_lifo() {
+        let mut fifo = inventory();
+        let sold = fifo
+            .reduce("VOO Shares", Decimal::from(15), Booking::Fifo, None)
+            .unwrap();
+        assert_eq!(sold.len(), 2);
+        assert_eq!(sold[1].quantity, Decimal::from(5));
+        assert_eq!(fifo.lots.len(), 1);
+        assert_eq!(fifo.lots[0].cost, Decimal::from(400));
+
+        let mut lifo = inventory();
+        let sold = lifo
+            .reduce("VOO Shares", Decimal::from(5), Booking::Lifo, None)
+            .unwrap();
+        assert_eq!(sold[0].cost, Decimal::from(400));
+        assert_eq!(lifo.lots[1].quantity, Decimal::from(5));
+    }
+
+    #[test]
+    fn test_reduce_average_and_specific_lot() {
+        let mut average = inventory();
+        let sold = average
+            .reduce("VOO Shares", Decimal::from(10), Booking::Average, None)
+            .unwrap();
+        let cost: Decimal = sold.iter().map(|l| l.cost_basis()).sum();
+        assert_eq!(cost, Decimal::from(3500));
+        assert_eq!(average.lots[0].quantity, Decimal::from(5));
+
+        let mut specific = inventory();
+        let date = NaiveDate::from_ymd_opt(2023, 3, 2);
+        let sold = specific
+            .reduce("VOO Shares", Decimal::from(3), Booking::Fifo, date)
+            .unwrap();
+        assert_eq!(sold[0].cost, Decimal::from(400));
+        assert_eq!(
+            specific.reduce("VOO Shares", Decimal::from(8), Booking::Fifo, date),
+            Err(Decimal::from(7))
+        );
+    }
+
+    #[test]
+    fn test_sale_is_long_term() {
+        let lot = inventory().lots.remove(0);
+        let sale = |date| Sale {
+            date,
+            lot: lot.clone(),
+            proceeds: Decimal::from(4000),
+            currency: "USD".to_string(),
+        };
+        assert!(!sale(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).is_long_term());
+        assert!(sale(NaiveDate::from_ymd_opt(2024, 3, 2).unwrap()).is_long_term());
+    }
+
+    #[test]
+    fn test_booking_from_str() {
+        assert_eq!(Booking::from_str("LIFO"), Ok(Booking::Lifo));
+        assert!(Booking::from_str("fifo").is_err());
+    }
+}
@@ -7,8 +7,11 @@
 //!
 //! - Double-entry *like* account keeping.
 //! - Uses [toml](https://toml.io/en/) format for the ledger.
-//! - Import of transactions from csv.
-//! - Balance and Journal reports.
+//! - Import of transactions and prices from csv.
+//! - Price updates from http, csv or command price sources.
+//! - Balance, Journal, Income Statement, Balance Sheet, Cash Flow, Trial Balance
+//!   and Register reports.
+//! - Text, csv, json, markdown and html output.
 //! - Grouping by month, quarter or year.
 //! - Commodity pricing.
 //!
@@ -18,14 +21,27 @@
 //! Usage: abacus-rs --ledger <LEDGER> [COMMAND]
 //!
 //! Commands:
-//!   accounts  List accounts
-//!   balances  Print account balance sheet report
-//!   journal   Print transactions journal report
-//!   import    Import transactions from csv
-//!   help      Print this message or the help of the given subcommand(s)
+//!   accounts          List accounts
+//!   check             Check the ledger for errors
+//!   balances          Print account balance sheet report
+//!   balance-sheet     Print balance sheet report
+//!   income-statement  Print income statement report
+//!   cashflow          Print cash flow statement report
+//!   trial-balance     Print trial balance report
+//!   journal           Print transactions journal report
+//!   register          Print postings register with running balance
+//!   lots              Print open lots of stocks, mutual funds and holdings
+//!   holdings          Print market value and unrealized gains of holdings
+//!   gains             Print realized gains of sales by tax year
+//!   prices            Print latest prices, or the price history of a commodity
+//!   import            Import transactions from csv
+//!   update-prices     Fetch prices of the account commodities from the price sources
+//!   import-prices     Import prices from csv
+//!   help              Print this message or the help of the given subcommand(s)
 //!
 //! Options:
 //!   -l, --ledger <LEDGER>  Path to ledger file or directory
+//!   -o, --output <OUTPUT>  Output format of the reports (text, csv, json, markdown or html) [default: text]
 //!   -h, --help             Print help
 //!   -V, --version          Print version
 //! ```
@@ -35,6 +51,19 @@
 //!
 //! Running any command requires declaring the path to the ledger.
 //!
+//! Every report can be printed as text, csv, json, markdown or html with the
+//! global `--output` option.
+//!
+//! ### Check
+//!
+//! ```bash
+//! Usage: abacus-rs --ledger <LEDGER> check
+//! ```
+//!
+//! Runs all the ledger checks and lists every problem found. The command exits
+//! with a non-zero code when problems are found, so it can be used in a git
+//! pre-commit hook.
+//!
 //! ### Print Balances
 //!
 //! ```bash
@@ -45,37 +74,77 @@
 //!   -t, --to <TO>             Filter transactions by end date
 //!   -p, --price <PRICE>       Price balances at specific currency
 //!   -g, --group <GROUP>       Group balances by period (M, Q or Y)
+//!   -d, --depth <DEPTH>       Collapse accounts below a depth of the hierarchy
+//!       --hide-closed         Hide accounts closed by the end date
+//!   -m, --mode <MODE>         Balance of each period (change, cumulative or historical)
 //!   -h, --help                Print help
 //!
 //! Account balances are printed for all accounts by default.
 //!
 //! ```bash
+//!   Accounts               Balance
 //! Assets
-//!     Savings Account       3245.00 USD
-//!     Crypto Wallet            0.56 BTC
+//!   Savings Account    3245.00 USD
+//!   Crypto Wallet         0.56 BTC
 //! Income
-//!     Salary               -2300.00 USD
+//!   Salary            -2300.00 USD
 //! Liabilities
-//!     Credit Card           -200.00 USD
+//!   Credit Card        -305.00 USD
 //! Expenses
-//!     Clothes                200.00 USD
-//!     Dining                  55.00 USD
+//!   Clothes             200.00 USD
+//!   Dining               55.00 USD
+//!   Groceries            80.00 USD
+//!   Home Repair          25.00 USD
+//! Equity
+//!   Opening Balances  -1000.00 USD
+//!                        -0.56 BTC
+//!     BTC                -0.56 BTC
 //! ```
 //!
 //! Specific account classes can be passed with the -c option to print a more typical
 //! balance sheet view. The amount can also be priced at a specific currency, provided
-//! there is a **price** entry for it in the ledger.
+//! there are **price** entries leading to it in the ledger. Crypto Wallet is in BTC,
+//! priced in USD, and converted into ARS through the USD price.
 //!
 //! ```bash
 //! > abacus-rs -l example/ balances -c Assets Liabilities -p ARS
 //!
+//!   Accounts                 Balance
 //! Assets
-//!     Savings Account    3017850.00 ARS
-//!     Crypto Wallet            0.56 ARS
+//!   Savings Account   3017850.00 ARS
+//!   Crypto Wallet    14488760.16 ARS
 //! Liabilities
-//!     Credit Card        -186000.00 ARS
+//!   Credit Card       -283650.00 ARS
 //! ```
 //!
+//! ### Balance Sheet
+//!
+//! Lists the Assets (including Stocks, MutualFunds, Holdings and Cash),
+//! Liabilities and Equity accounts as of the `--to` date, with a retained
+//! earnings line summing all income and expenses, and checks that assets equal
+//! liabilities plus equity.
+//!
+//! ### Income Statement
+//!
+//! Lists the Income and Expenses accounts with natural signs, the total of each
+//! section and the net income of each period. Accepts the `--from`, `--to`,
+//! `--price`, `--group` and `--depth` options of the balances report.
+//!
+//! ### Cash Flow
+//!
+//! Lists the cash received and paid by the cash accounts in a period, grouped
+//! by the account on the other side of each transaction, between the opening and
+//! closing cash balances. Cash accounts are selected with the `--class` or
+//! `--account` options, and default to the Cash accounts, or to the Assets
+//! accounts when the ledger has no Cash accounts. Opening balances are part of
+//! the opening cash balance.
+//!
+//! ### Trial Balance
+//!
+//! Lists every account with its debit and credit totals and balance. The totals
+//! of each currency must be zero, the report ends listing the currencies where
+//! debits and credits differ.
+//!
 //! ### Print Journal
 //!
 //! ```bash
@@ -100,12 +169,46 @@
 //! ```bash
 //! > abacus-rs -l example/ journal -a Dining
 //!
-//! 2023-10-10 | Dining             |       20.00 | RESTAURANT X
-//! 2023-10-10 | Savings Account    |      -20.00 |
-//! 2023-10-11 | Dining             |       35.00 | RESTAURANT Y
-//! 2023-10-11 | Savings Account    |      -35.00 |
+//! Date        Account              Amount  Payee
+//! 2023-10-10  Dining            20.00 USD  RESTAURANT X
+//! 2023-10-10  Savings Account  -20.00 USD
+//! 2023-10-11  Dining            35.00 USD  RESTAURANT Y
+//! 2023-10-11  Savings Account  -35.00 USD
 //! ```
 //!
+//! ### Register
+//!
+//! Lists the postings to one or more accounts, and their subaccounts, in date
+//! order with a running balance that starts from the balance before `--from`.
+//! Amounts can be priced at a specific currency with `--price`.
+//!
+//! ### Lots
+//!
+//! Lists the open lots of the Stocks, MutualFunds and Holdings accounts, with
+//! the purchase date, quantity, unit cost and cost basis of each one. Sales take
+//! units from the lots by the account `booking` method, FIFO, LIFO or AVERAGE,
+//! or from the lot bought on the date given with `lot`.
+//!
+//! ### Holdings
+//!
+//! Lists the units held in each lot account with their average cost, market
+//! value at the newest price at or before `--to` and unrealized gain, followed
+//! by the portfolio totals.
+//!
+//! ### Gains
+//!
+//! Lists the realized gain of each sale of units of the lot accounts, with its
+//! proceeds, cost basis and holding term, and the short-term, long-term and total
+//! gains of each tax year. `--price` converts the amounts into a currency at the
+//! prices of the sale and purchase dates.
+//!
+//! ### Prices
+//!
+//! Lists the latest price of each commodity in each currency as of `--to`, one
+//! column per currency, or the price history of a commodity with `--commodity`.
+//! Prices older than `--max-age` days, the `max_price_age` option or 30 days
+//! by default, are flagged as stale.
+//!
 //! ### Import transactions
 //!
 //! ```bash
@@ -139,19 +242,51 @@
 //! ...
 //! Import complete
 //! ```
+//!
+//! ### Import prices
+//!
+//! Appends the prices of a csv file to the ledger file as `[[price]]` entries,
+//! skipping the prices already declared for the same commodity, currency and
+//! date. Column names default to date, commodity, price and currency and can be
+//! changed with the `--date-column`, `--commodity-column`, `--price-column` and
+//! `--currency-column` options. Files without a commodity or currency column
+//! take them from `--commodity` and `--currency`.
+//!
+//! ```bash
+//! > abacus-rs -l example/prices.toml import-prices -c ~/Downloads/VOO.csv \
+//!     --date-column Date --price-column Close --commodity VOO --currency USD
+//! Import start
+//! Skipped: 2023-09-30 VOO 390.50 USD
+//! Imported: 2023-10-31 VOO 380.10 USD
+//! Import complete
+//! ```
+//!
+//! ### Update prices
+//!
+//! Asks the `[[price_source]]` entries of the ledger for the price of every
+//! commodity held in an account on the `--date`, today by default, and appends
+//! the new prices to the ledger file, or to the `--file` when the ledger is a
+//! directory. Sources get the price from a JSON document over http, a csv file
+//! or a shell command, see [abacus_rs::pricesource].
+//!
+//! ```bash
+//! > abacus-rs -l example/ update-prices -d 2023-10-02 -f example/prices.toml
+//! Import start
+//! Imported: 2023-10-02 BTC 27510.40 USD
+//! Skipped: 2023-10-02 USD 930.00 ARS
+//! Import complete
+//! ```
 
+use abacus_rs::csvimporter::{append_prices, import_prices, import_transactions, PriceCsvFormat};
+use abacus_rs::error::LedgerError;
+use abacus_rs::ledger::BalanceMode;
+use abacus_rs::read_ledger_files;
+use abacus_rs::report::{OutputFormat, Report};
+use chrono::{Local, NaiveDate};
 use clap::{Parser, Subcommand};
-use csvimporter::import_transactions;
-use ledger::Ledger;
 use std::error::Error;
-use utils::read_ledger_files;
-
-pub mod accounts;
-pub mod csvimporter;
-pub mod ledger;
-pub mod price;
-pub mod transaction;
-pub mod utils;
+use std::path::Path;
+use std::process;
 
 #[derive(Parser, Debug)]
 #[command(author = "Federico Carles", version = "0.1", about, long_about = None)]
@@ -159,6 +294,9 @@ pub struct Args {
     /// Path to ledger file or directory
     #[arg(short, long)]
     ledger: String,
+    /// Output format of the reports (text, csv, json, markdown or html)
+    #[arg(short, long, global = true, default_value = "text")]
+    output: OutputFormat,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -166,7 +304,22 @@ pub struct Args {
 #[derive(Debug, Clone, Subcommand)]
 pub enum Commands {
     /// List accounts
-    Accounts {},
+    Accounts {
+        /// Show accounts down to a depth of the hierarchy
+        #[arg(short, long)]
+        depth: Option<usize>,
+        /// Hide closed accounts
+        #[arg(long)]
+        hide_closed: bool,
+        /// Show accounts open after a start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
+        /// Show accounts open before an end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+    },
+    /// Check the ledger for errors
+    Check {},
     /// Print account balance sheet report
     Balances {
         /// Filter accounts by account type
@@ -174,25 +327,88 @@ pub enum Commands {
         class: Option<Vec<String>>,
         /// Filter transactions by start date
         #[arg(short, long)]
-        from: Option<String>,
+        from: Option<NaiveDate>,
+        /// Filter transactions by end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Price balances at specific currency
+        #[arg(short, long)]
+        price: Option<String>,
+        /// Group balances by period (M, Q or Y)
+        #[arg(short, long)]
+        group: Option<String>,
+        /// Collapse accounts below a depth of the hierarchy
+        #[arg(short, long)]
+        depth: Option<usize>,
+        /// Hide accounts closed by the end date
+        #[arg(long)]
+        hide_closed: bool,
+        /// Balance of each period (change, cumulative or historical)
+        #[arg(short, long, default_value = "change")]
+        mode: BalanceMode,
+    },
+    /// Print balance sheet report
+    BalanceSheet {
+        /// Balances as of the end of date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Price balances at specific currency
+        #[arg(short, long)]
+        price: Option<String>,
+        /// Collapse accounts below a depth of the hierarchy
+        #[arg(short, long)]
+        depth: Option<usize>,
+    },
+    /// Print income statement report
+    IncomeStatement {
+        /// Filter transactions by start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
         /// Filter transactions by end date
         #[arg(short, long)]
-        to: Option<String>,
+        to: Option<NaiveDate>,
         /// Price balances at specific currency
         #[arg(short, long)]
         price: Option<String>,
         /// Group balances by period (M, Q or Y)
         #[arg(short, long)]
         group: Option<String>,
+        /// Collapse accounts below a depth of the hierarchy
+        #[arg(short, long)]
+        depth: Option<usize>,
+    },
+    /// Print cash flow statement report
+    Cashflow {
+        /// Filter transactions by start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
+        /// Filter transactions by end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Cash accounts by account type (Cash by default, or Assets without Cash accounts)
+        #[arg(short, long, num_args(0..))]
+        class: Option<Vec<String>>,
+        /// Cash accounts by account name, including their subaccounts
+        #[arg(short, long, num_args(0..))]
+        account: Option<Vec<String>>,
+    },
+    /// Print trial balance report
+    TrialBalance {
+        /// Filter transactions by start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
+        /// Filter transactions by end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
     },
     /// Print transactions journal report
     Journal {
         /// Filter transactions by start date
         #[arg(short, long)]
-        from: Option<String>,
+        from: Option<NaiveDate>,
         /// Filter transactions by end date
         #[arg(short, long)]
-        to: Option<String>,
+        to: Option<NaiveDate>,
         /// Filter accounts by account type
         #[arg(short, long)]
         class: Option<String>,
@@ -203,6 +419,69 @@ pub enum Commands {
         #[arg(short, long)]
         payee: Option<String>,
     },
+    /// Print postings register with running balance
+    Register {
+        /// Accounts to register, including their subaccounts
+        #[arg(short, long, num_args(1..), required = true)]
+        account: Vec<String>,
+        /// Filter transactions by start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
+        /// Filter transactions by end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Price amounts at specific currency
+        #[arg(short, long)]
+        price: Option<String>,
+    },
+    /// Print open lots of stocks, mutual funds and holdings
+    Lots {
+        /// Lot accounts by account name, including their subaccounts
+        #[arg(short, long, num_args(0..))]
+        account: Option<Vec<String>>,
+        /// Lots held at the end of date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+    },
+    /// Print market value and unrealized gains of holdings
+    Holdings {
+        /// Holdings and prices as of the end of date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Lot accounts by account name, including their subaccounts
+        #[arg(short, long, num_args(0..))]
+        account: Option<Vec<String>>,
+        /// Price holdings at specific currency
+        #[arg(short, long)]
+        price: Option<String>,
+    },
+    /// Print realized gains of sales by tax year
+    Gains {
+        /// Filter sales by start date
+        #[arg(short, long)]
+        from: Option<NaiveDate>,
+        /// Filter sales by end date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Lot accounts by account name, including their subaccounts
+        #[arg(short, long, num_args(0..))]
+        account: Option<Vec<String>>,
+        /// Price gains at specific currency
+        #[arg(short, long)]
+        price: Option<String>,
+    },
+    /// Print latest prices, or the price history of a commodity
+    Prices {
+        /// Price history of a commodity
+        #[arg(short, long)]
+        commodity: Option<String>,
+        /// Prices as of the end of date
+        #[arg(short, long)]
+        to: Option<NaiveDate>,
+        /// Flag prices older than a number of days (max_price_age or 30 by default)
+        #[arg(long)]
+        max_age: Option<u32>,
+    },
     /// Import transactions from csv
     Import {
         /// CSV file with transactions to import
@@ -212,30 +491,192 @@ pub enum Commands {
         #[arg(short, long)]
         format: Option<String>,
     },
+    /// Fetch prices of the account commodities from the price sources
+    UpdatePrices {
+        /// Date of the prices [default: today]
+        #[arg(short, long)]
+        date: Option<NaiveDate>,
+        /// Ledger file to append the prices to [default: the ledger file]
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+    /// Import prices from csv
+    ImportPrices {
+        /// CSV file with prices to import
+        #[arg(short, long)]
+        csv: String,
+        /// Date format
+        #[arg(short, long, default_value = "%Y-%m-%d")]
+        format: String,
+        /// Name of the date column
+        #[arg(long, default_value = "date")]
+        date_column: String,
+        /// Name of the commodity column
+        #[arg(long, default_value = "commodity")]
+        commodity_column: String,
+        /// Name of the price column
+        #[arg(long, default_value = "price")]
+        price_column: String,
+        /// Name of the currency column
+        #[arg(long, default_value = "currency")]
+        currency_column: String,
+        /// Commodity of all the prices, for files without a commodity column
+        #[arg(long)]
+        commodity: Option<String>,
+        /// Currency of all the prices, for files without a currency column
+        #[arg(long)]
+        currency: Option<String>,
+        /// Ledger file to append the prices to [default: the ledger file]
+        #[arg(long)]
+        file: Option<String>,
+    },
+}
+
+/// The file new prices are appended to, the ledger itself unless it is a
+/// directory.
+fn price_file(file: Option<String>, ledger: &str) -> Result<String, Box<dyn Error>> {
+    match file {
+        Some(file) => Ok(file),
+        None if Path::new(ledger).is_dir() => {
+            Err("A --file to append the prices to is required".into())
+        }
+        None => Ok(ledger.to_string()),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn run() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
     let ledger = read_ledger_files(&args.ledger);
 
-    match args.command {
-        Some(Commands::Accounts {}) => ledger?.print_accounts(),
+    let report = match args.command {
+        Some(Commands::Accounts {
+            depth,
+            hide_closed,
+            from,
+            to,
+        }) => Report::from(&ledger?.accounts_report(depth, hide_closed, from, to)),
+        Some(Commands::Check {}) => {
+            ledger?.check()?;
+            println!("No errors found");
+            return Ok(());
+        }
         Some(Commands::Balances {
             from,
             to,
             class,
             price,
             group,
-        }) => ledger?.print_balances(from, to, class, price, group),
+            depth,
+            hide_closed,
+            mode,
+        }) => Report::from(&ledger?.balances_report(
+            from,
+            to,
+            class,
+            price,
+            group,
+            depth,
+            hide_closed,
+            mode,
+        )?),
+        Some(Commands::BalanceSheet { to, price, depth }) => {
+            ledger?.balance_sheet_report(to, price, depth)?
+        }
+        Some(Commands::Cashflow {
+            from,
+            to,
+            class,
+            account,
+        }) => ledger?.cashflow_report(from, to, class, account)?,
+        Some(Commands::TrialBalance { from, to }) => ledger?.trial_balance_report(from, to)?,
+        Some(Commands::IncomeStatement {
+            from,
+            to,
+            price,
+            group,
+            depth,
+        }) => ledger?.income_statement_report(from, to, price, group, depth)?,
         Some(Commands::Journal {
             from,
             to,
             class,
             account,
             payee,
-        }) => ledger?.print_journal(from, to, class, account, payee),
-        Some(Commands::Import { csv, format }) => import_transactions(&csv, &args.ledger, format)?,
-        None => {}
-    }
+        }) => Report::from(&ledger?.journal_report(from, to, class, account, payee)?),
+        Some(Commands::Register {
+            account,
+            from,
+            to,
+            price,
+        }) => ledger?.register_report(account, from, to, price)?,
+        Some(Commands::Lots { account, to }) => ledger?.lots_report(account, to)?,
+        Some(Commands::Holdings { to, account, price }) => {
+            ledger?.holdings_report(to, account, price)?
+        }
+        Some(Commands::Gains {
+            from,
+            to,
+            account,
+            price,
+        }) => ledger?.gains_report(from, to, account, price)?,
+        Some(Commands::Prices {
+            commodity,
+            to,
+            max_age,
+        }) => ledger?.prices_report(commodity, to, max_age)?,
+        Some(Commands::Import { csv, format }) => {
+            import_transactions(&csv, &args.ledger, format)?;
+            return Ok(());
+        }
+        Some(Commands::UpdatePrices { date, file }) => {
+            let ledger = ledger?;
+            let date = date.unwrap_or_else(|| Local::now().date_naive());
+            let file = price_file(file, &args.ledger)?;
+            // Prices found are kept even if some sources failed
+            let (prices, errors) = ledger.fetch_prices(date);
+            append_prices(&file, prices, ledger.prices())?;
+            LedgerError::from_errors(errors)?;
+            return Ok(());
+        }
+        Some(Commands::ImportPrices {
+            csv,
+            format,
+            date_column,
+            commodity_column,
+            price_column,
+            currency_column,
+            commodity,
+            currency,
+            file,
+        }) => {
+            let file = price_file(file, &args.ledger)?;
+            // A new ledger file has no prices to skip
+            let existing = match ledger {
+                Ok(ledger) => ledger.prices().to_vec(),
+                Err(LedgerError::Io { .. }) => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let format = PriceCsvFormat {
+                date_column,
+                commodity_column,
+                price_column,
+                currency_column,
+                commodity,
+                currency,
+                date_format: format,
+            };
+            import_prices(&csv, &file, &existing, &format)?;
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+    print!("{}", args.output.formatter().format(&report)?);
     Ok(())
 }
@@ -16,37 +16,245 @@
 //! price = 390.50
 //! currency = "USD"
 //! ```
+//!
+//! Reports look prices up in a [PriceDb], which answers with the most recent
+//! price on or before the valuation date. Prices older than the optional
+//! `max_price_age` ledger option, in days, are not used.
+//!
+//! Prices also convert in the inverse direction, and commodities without a
+//! price in a currency are converted through other commodities, like BTC into
+//! ARS through BTC in USD and USD in ARS.
+//!
+//! ```toml
+//! [options]
+//! max_price_age = 30
+//! ```
 
+use crate::error::Location;
 use chrono::prelude::*;
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Age in days of the prices flagged as stale by the prices report, when the
+/// ledger has no `max_price_age`.
+pub const STALE_PRICE_DAYS: u32 = 30;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Price {
     pub date: NaiveDate,
     pub commodity: String,
-    pub price: f32,
+    pub price: Decimal,
     pub currency: String,
+    pub location: Option<Location>,
 }
 
 impl Price {
-    pub fn new(date: NaiveDate, commodity: String, price: f32, currency: String) -> Self {
+    pub fn new(date: NaiveDate, commodity: String, price: Decimal, currency: String) -> Self {
         Self {
-            date: date,
+            date,
             commodity: commodity.replace('"', ""),
-            price: price,
+            price,
             currency: currency.replace('"', ""),
+            location: None,
         }
     }
 }
 
+/// The prices of the ledger by commodity and currency, in date order.
+#[derive(Debug, Clone, Default)]
+pub struct PriceDb {
+    prices: HashMap<(String, String), Vec<(NaiveDate, Decimal)>>,
+    max_age: Option<u32>,
+}
+
+impl PriceDb {
+    pub fn new(prices: &[Price]) -> Self {
+        let mut db = Self::default();
+        for p in prices {
+            db.prices
+                .entry((p.commodity.clone(), p.currency.clone()))
+                .or_default()
+                .push((p.date, p.price));
+        }
+        for history in db.prices.values_mut() {
+            history.sort_by_key(|(date, _)| *date);
+        }
+        db
+    }
+
+    /// Ignores prices older than `days` before the date they are looked up at.
+    pub fn with_max_age(mut self, days: Option<u32>) -> Self {
+        self.max_age = days;
+        self
+    }
+
+    /// Price of the commodity in the currency on the date, the most recent one
+    /// declared on or before it.
+    pub fn price(&self, commodity: &str, currency: &str, date: NaiveDate) -> Option<Decimal> {
+        let (price_date, price) = self.latest(commodity, currency, date)?;
+        match self.max_age {
+            Some(days) if (date - price_date).num_days() > days as i64 => None,
+            _ => Some(price),
+        }
+    }
+
+    /// Date and price of the most recent price declared on or before the date,
+    /// however old it is.
+    pub fn latest(
+        &self,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Option<(NaiveDate, Decimal)> {
+        self.history(commodity, currency)
+            .iter()
+            .rev()
+            .find(|(d, _)| *d <= date)
+            .copied()
+    }
+
+    /// Prices of the commodity in the currency, in date order.
+    pub fn history(&self, commodity: &str, currency: &str) -> &[(NaiveDate, Decimal)] {
+        self.prices
+            .get(&(commodity.to_string(), currency.to_string()))
+            .map(|h| h.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Commodity and currency of every declared price, sorted.
+    pub fn pairs(&self) -> Vec<(&str, &str)> {
+        self.prices
+            .keys()
+            .map(|(c, cur)| (c.as_str(), cur.as_str()))
+            .sorted()
+            .collect()
+    }
+
+    /// Rate to convert the commodity into the currency on the date. Without a
+    /// direct price, the rate chains the fewest prices, used in either
+    /// direction, that lead from the commodity to the currency.
+    pub fn rate(&self, commodity: &str, currency: &str, date: NaiveDate) -> Option<Decimal> {
+        if commodity == currency {
+            return Some(Decimal::ONE);
+        }
+        if let Some(price) = self.price(commodity, currency, date) {
+            return Some(price);
+        }
+
+        let mut edges: HashMap<&str, Vec<(&str, Decimal)>> = HashMap::new();
+        for (c, cur) in self.pairs() {
+            match self.price(c, cur, date) {
+                Some(price) if !price.is_zero() => {
+                    edges.entry(c).or_default().push((cur, price));
+                    edges
+                        .entry(cur)
+                        .or_default()
+                        .push((c, Decimal::ONE / price));
+                }
+                _ => {}
+            }
+        }
+
+        let mut visited: HashSet<&str> = HashSet::from([commodity]);
+        let mut queue: VecDeque<(&str, Decimal)> = VecDeque::from([(commodity, Decimal::ONE)]);
+        while let Some((node, rate)) = queue.pop_front() {
+            for (next, price) in edges.get(node).into_iter().flatten() {
+                if *next == currency {
+                    return Some(rate * price);
+                }
+                if visited.insert(next) {
+                    queue.push_back((next, rate * price));
+                }
+            }
+        }
+        None
+    }
+
+    /// Converts an amount of the commodity into the currency at the rate of
+    /// the date.
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Option<Decimal> {
+        self.rate(commodity, currency, date).map(|r| amount * r)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_price_db() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2023, m, d).unwrap();
+        let prices = vec![
+            Price::new(date(9, 30), "VOO".into(), Decimal::from(390), "USD".into()),
+            Price::new(date(6, 30), "VOO".into(), Decimal::from(370), "USD".into()),
+        ];
+        let db = PriceDb::new(&prices);
+
+        assert_eq!(db.price("VOO", "USD", date(6, 29)), None);
+        assert_eq!(db.price("VOO", "USD", date(8, 1)), Some(Decimal::from(370)));
+        assert_eq!(
+            db.price("VOO", "USD", date(12, 31)),
+            Some(Decimal::from(390))
+        );
+        assert_eq!(db.price("VOO", "EUR", date(12, 31)), None);
+        assert_eq!(
+            db.convert(Decimal::from(2), "VOO", "USD", date(10, 1)),
+            Some(Decimal::from(780))
+        );
+        assert_eq!(
+            db.convert(Decimal::from(2), "USD", "USD", date(1, 1)),
+            Some(Decimal::from(2))
+        );
+
+        let db = db.with_max_age(Some(30));
+        assert_eq!(
+            db.rate("USD", "VOO", date(10, 30)),
+            Some(Decimal::ONE / Decimal::from(390))
+        );
+        assert_eq!(
+            db.price("VOO", "USD", date(10, 30)),
+            Some(Decimal::from(390))
+        );
+        assert_eq!(db.price("VOO", "USD", date(10, 31)), None);
+        assert_eq!(
+            db.latest("VOO", "USD", date(10, 31)),
+            Some((date(9, 30), Decimal::from(390)))
+        );
+        assert_eq!(db.history("VOO", "USD")[0].0, date(6, 30));
+        assert_eq!(db.pairs(), vec![("VOO", "USD")]);
+    }
+
+    #[test]
+    fn test_price_db_rate_through_other_commodities() {
+        let date = NaiveDate::from_ymd_opt(2023, 10, 2).unwrap();
+        let prices = vec![
+            Price::new(date, "BTC".into(), Decimal::from(27000), "USD".into()),
+            Price::new(date, "USD".into(), Decimal::from(900), "ARS".into()),
+            Price::new(date, "EUR".into(), Decimal::new(11, 1), "USD".into()),
+        ];
+        let db = PriceDb::new(&prices);
+
+        assert_eq!(db.rate("BTC", "ARS", date), Some(Decimal::from(24_300_000)));
+        assert_eq!(
+            db.rate("ARS", "EUR", date).map(|r| r.round_dp(6)),
+            Some(Decimal::new(1010, 6))
+        );
+        assert_eq!(db.rate("BTC", "GBP", date), None);
+        assert_eq!(db.rate("BTC", "USD", date - chrono::Days::new(1)), None);
+    }
+
     #[test]
     fn test_price_new() {
         let date = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let commodity = "Gold".to_string();
-        let price = 1500.0;
+        let price = Decimal::from(1500);
         let currency = "USD".to_string();
 
         let price_data = Price::new(date, commodity.clone(), price, currency.clone());
@@ -61,12 +269,12 @@ mod tests {
     fn test_price_equality() {
         let date1 = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let commodity1 = "Gold".to_string();
-        let price1 = 1500.0;
+        let price1 = Decimal::from(1500);
         let currency1 = "USD".to_string();
 
         let date2 = NaiveDate::from_ymd_opt(2023, 10, 14).unwrap();
         let commodity2 = "Gold".to_string();
-        let price2 = 1550.0;
+        let price2 = Decimal::from(1550);
         let currency2 = "USD".to_string();
 
         let price_data1 = Price::new(date1, commodity1.clone(), price1, currency1.clone());
@@ -84,7 +292,7 @@ mod tests {
     fn test_price_clone() {
         let date = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let commodity = "Gold".to_string();
-        let price = 1500.0;
+        let price = Decimal::from(1500);
         let currency = "USD".to_string();
 
         let price_data = Price::new(date, commodity.clone(), price, currency.clone());
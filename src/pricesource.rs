@@ -0,0 +1,364 @@
+//! This module defines the [PriceSource] trait and its providers.
+//!
+//! The `update-prices` command asks the price sources of the ledger for the
+//! price of every commodity held in an account, and appends the new prices to
+//! the ledger. Sources are declared with a **type**, the **currency** they
+//! price in and, optionally, the **commodities** they price, all the account
+//! commodities by default. The `{commodity}`, `{currency}` and `{date}`
+//! placeholders of urls, files and commands are replaced by the values of
+//! each query.
+//!
+//! - `http` sources get a JSON document from the **url** and read the price
+//!   from the dotted **path** of fields and array indices, the whole document
+//!   by default.
+//! - `csv` sources read the newest price on or before the date from a csv
+//!   **file** with date, commodity, price and currency columns. Files of a
+//!   single commodity or currency can leave those columns out, and dates in
+//!   other formats are read with **date_format**.
+//! - `command` sources run a shell **command** that prints the price. The
+//!   values are passed to the shell as the `$1`, `$2` and `$3` arguments the
+//!   placeholders stand for, so they are never run as part of the command.
+//!
+//! ```toml
+//! [[price_source]]
+//! type = "http"
+//! currency = "USD"
+//! commodities = ["VOO", "BTC"] # optional
+//! url = "https://quotes.example.com/{commodity}?currency={currency}"
+//! path = "quote.price"         # optional
+//!
+//! [[price_source]]
+//! type = "csv"
+//! currency = "ARS"
+//! file = "quotes/{commodity}.csv"
+//! date_format = "%d/%m/%Y"     # optional
+//!
+//! [[price_source]]
+//! type = "command"
+//! currency = "EUR"
+//! command = "scripts/quote.sh {commodity} {currency}"
+//! ```
+
+use crate::csvimporter::{read_prices, PriceCsvFormat};
+use crate::error::Location;
+use crate::price::Price;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Seconds to wait for the response of a http source.
+const HTTP_TIMEOUT: u64 = 30;
+
+/// A provider of commodity prices.
+pub trait PriceSource: fmt::Debug {
+    /// Price of the commodity in the currency on the date, or the newest one
+    /// before it. None if the source has no price for the commodity.
+    fn fetch(
+        &self,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Price>, String>;
+}
+
+/// The providers of the `type` field of a `[[price_source]]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceSourceType {
+    Http,
+    Csv,
+    Command,
+}
+
+impl FromStr for PriceSourceType {
+    type Err = String;
+    fn from_str(input: &str) -> Result<PriceSourceType, Self::Err> {
+        match input {
+            "http" => Ok(PriceSourceType::Http),
+            "csv" => Ok(PriceSourceType::Csv),
+            "command" => Ok(PriceSourceType::Command),
+            _ => Err(format!(
+                "Invalid price source type {}, expected http, csv or command",
+                input
+            )),
+        }
+    }
+}
+
+/// A price source of the ledger and the commodities it prices.
+#[derive(Debug)]
+pub struct PriceSourceEntry {
+    pub source: Box<dyn PriceSource>,
+    pub currency: String,
+    /// Commodities priced by the source, all the account commodities if None.
+    pub commodities: Option<Vec<String>>,
+    pub location: Option<Location>,
+}
+
+impl PriceSourceEntry {
+    /// Returns true if the source prices the commodity.
+    pub fn prices(&self, commodity: &str) -> bool {
+        commodity != self.currency
+            && self
+                .commodities
+                .as_ref()
+                .is_none_or(|c| c.iter().any(|c| c == commodity))
+    }
+}
+
+/// Replaces the placeholders of a url or file template.
+fn fill(template: &str, commodity: &str, currency: &str, date: NaiveDate) -> String {
+    template
+        .replace("{commodity}", commodity)
+        .replace("{currency}", currency)
+        .replace("{date}", &date.to_string())
+}
+
+/// Prices read from a JSON document served over http.
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    pub url: String,
+    /// Dotted path of the price in the document, like `quote.price` or
+    /// `data.0.close`.
+    pub path: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl HttpSource {
+    pub fn new(url: String, path: Option<String>) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(HTTP_TIMEOUT))
+            .build();
+        Self { url, path, agent }
+    }
+
+    /// The price at the path of the document.
+    fn price_in(&self, document: &Value) -> Result<Decimal, String> {
+        let mut value = document;
+        for field in self.path.iter().flat_map(|p| p.split('.')) {
+            let next = match value {
+                Value::Array(items) => field.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => value.get(field),
+            };
+            value = next.ok_or(format!("Field {} not found in the response", field))?;
+        }
+        let number = match value {
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => s.clone(),
+            v => return Err(format!("Expected a price, found {}", v)),
+        };
+        Decimal::from_str(&number).map_err(|_| format!("Invalid price {}", number))
+    }
+}
+
+impl PriceSource for HttpSource {
+    fn fetch(
+        &self,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Price>, String> {
+        let url = fill(&self.url, commodity, currency, date);
+        let body = match self.agent.get(&url).call() {
+            Ok(response) => response.into_string().map_err(|e| e.to_string())?,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        let document: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+        let price = self.price_in(&document)?;
+        Ok(Some(Price::new(
+            date,
+            commodity.to_string(),
+            price,
+            currency.to_string(),
+        )))
+    }
+}
+
+/// Prices read from a csv file, see [read_prices].
+#[derive(Debug, Clone)]
+pub struct CsvSource {
+    pub file: String,
+    pub date_format: Option<String>,
+}
+
+impl PriceSource for CsvSource {
+    fn fetch(
+        &self,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Price>, String> {
+        let path = fill(&self.file, commodity, currency, date);
+        let file = File::open(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let mut format = PriceCsvFormat {
+            commodity: Some(commodity.to_string()),
+            currency: Some(currency.to_string()),
+            ..Default::default()
+        };
+        if let Some(date_format) = &self.date_format {
+            format.date_format = date_format.clone();
+        }
+        let prices = read_prices(file, &format).map_err(|e| e.to_string())?;
+        Ok(prices
+            .into_iter()
+            .filter(|p| p.commodity == commodity && p.currency == currency && p.date <= date)
+            .max_by_key(|p| p.date))
+    }
+}
+
+/// Prices printed by a shell command.
+#[derive(Debug, Clone)]
+pub struct CommandSource {
+    pub command: String,
+}
+
+impl CommandSource {
+    /// The command with its placeholders replaced by the positional
+    /// parameters that hold their values.
+    fn script(&self) -> String {
+        self.command
+            .replace("{commodity}", "\"$1\"")
+            .replace("{currency}", "\"$2\"")
+            .replace("{date}", "\"$3\"")
+    }
+}
+
+impl PriceSource for CommandSource {
+    fn fetch(
+        &self,
+        commodity: &str,
+        currency: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Price>, String> {
+        let command = fill(&self.command, commodity, currency, date);
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(self.script())
+            .args(["sh", commodity, currency, &date.to_string()])
+            .output()
+            .map_err(|e| format!("Failed to run {}: {}", command, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                command,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match stdout.trim() {
+            "" => Ok(None),
+            price => Decimal::from_str(price)
+                .map(|p| Some(Price::new(date, commodity.into(), p, currency.into())))
+                .map_err(|_| format!("Invalid price {}", price)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves the JSON body to the requests of a local server, and returns the
+    /// url of the server. Paths ending in `missing` get a 404 response.
+    pub(crate) fn mock_server(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let response = match request.split(' ').nth(1) {
+                    Some(path) if path.ends_with("missing") => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+                    }
+                    _ => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 10, 2).unwrap()
+    }
+
+    #[test]
+    fn test_http_source() {
+        let url = mock_server(r#"{"quote": {"prices": [{"close": 390.50}, {"close": "391"}]}}"#);
+        let source = |path: &str| {
+            HttpSource::new(
+                format!("{}/{}/{{commodity}}", url, path),
+                Some("quote.prices.0.close".to_string()),
+            )
+        };
+
+        let price = source("quote")
+            .fetch("VOO", "USD", date())
+            .unwrap()
+            .unwrap();
+        assert_eq!(price.price, Decimal::new(39050, 2));
+        assert_eq!(price.date, date());
+        assert_eq!(source("quote").fetch("missing", "USD", date()), Ok(None));
+
+        let wrong_path = HttpSource {
+            path: Some("quote.close".to_string()),
+            ..source("quote")
+        };
+        assert!(wrong_path.fetch("VOO", "USD", date()).is_err());
+    }
+
+    #[test]
+    fn test_csv_source() {
+        let dir = std::env::temp_dir().join("abacus-csv-source");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("VOO.csv"),
+            "date,price\n2023-09-29,388.10\n2023-09-30,390.50\n2023-10-03,391.00\n",
+        )
+        .unwrap();
+        let source = CsvSource {
+            file: format!("{}/{{commodity}}.csv", dir.display()),
+            date_format: None,
+        };
+
+        let price = source.fetch("VOO", "USD", date()).unwrap().unwrap();
+        assert_eq!(price.date, NaiveDate::from_ymd_opt(2023, 9, 30).unwrap());
+        assert_eq!(price.currency, "USD");
+        assert!(source.fetch("BTC", "USD", date()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_command_source() {
+        let source = CommandSource {
+            command: "echo {commodity} {currency} {date} | wc -w".to_string(),
+        };
+        let price = source.fetch("VOO", "USD", date()).unwrap().unwrap();
+        assert_eq!(price.price, Decimal::from(3));
+
+        // values are arguments of the command, not shell code
+        let price = source.fetch("VOO;exit 1", "USD", date()).unwrap().unwrap();
+        assert_eq!(price.price, Decimal::from(4));
+
+        let failing = CommandSource {
+            command: "exit 1".to_string(),
+        };
+        assert!(failing.fetch("VOO", "USD", date()).is_err());
+    }
+}
@@ -0,0 +1,815 @@
+//! This module defines the [Report] table returned by the ledger reports and
+//! the [Formatter]s that render it.
+//!
+//! A report is a list of columns and rows. Section rows title a group of rows
+//! (the Assets accounts of a balance sheet), total rows close it, and the
+//! amounts of a row share a single currency. Notes are free text lines printed
+//! after the table, like the accounting equation check of the balance sheet.
+//!
+//! The output format of every command is selected with the global `--output`
+//! option, one of text, csv, json, markdown or html.
+//!
+//! The balances, journal and accounts reports are also returned as typed data,
+//! [BalanceReport], [JournalReport] and [AccountList], which convert into a
+//! [Report] for rendering.
+
+use crate::accounts::{Account, AccountType, ACCOUNT_SEPARATOR};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::str::FromStr;
+
+/// A value of a report row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Date(NaiveDate),
+    Amount(Decimal),
+    /// A number of units, shown at full precision and without the row currency.
+    Quantity(Decimal),
+    /// An account of a balance row, by its full path. The label is the
+    /// indented last segment of the path shown in text output, empty on the
+    /// rows of the other currencies of the account.
+    Account {
+        path: String,
+        label: String,
+    },
+    Empty,
+}
+
+impl Cell {
+    /// The cell as text, with amounts rounded to two decimals.
+    fn display(&self) -> String {
+        match self {
+            Cell::Text(t) => t.to_string(),
+            Cell::Date(d) => d.to_string(),
+            Cell::Amount(a) => format!("{:.2}", a),
+            Cell::Quantity(q) => q.normalize().to_string(),
+            Cell::Account { path, .. } => path.to_string(),
+            Cell::Empty => String::new(),
+        }
+    }
+
+    /// The cell as shown in text output, with accounts by their label.
+    pub fn text(&self) -> String {
+        match self {
+            Cell::Account { label, .. } => label.to_string(),
+            c => c.display(),
+        }
+    }
+
+    /// The cell as text, with amounts at full precision.
+    fn raw(&self) -> String {
+        match self {
+            Cell::Amount(a) => a.to_string(),
+            c => c.display(),
+        }
+    }
+
+    fn json(&self) -> Value {
+        match self {
+            Cell::Amount(a) | Cell::Quantity(a) => json!(a.to_string()),
+            Cell::Empty => Value::Null,
+            c => json!(c.display()),
+        }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl From<NaiveDate> for Cell {
+    fn from(date: NaiveDate) -> Self {
+        Cell::Date(date)
+    }
+}
+
+impl From<Decimal> for Cell {
+    fn from(amount: Decimal) -> Self {
+        Cell::Amount(amount)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowKind {
+    Section,
+    Entry,
+    Total,
+    Blank,
+}
+
+impl RowKind {
+    fn name(&self) -> &str {
+        match self {
+            RowKind::Section => "section",
+            RowKind::Entry => "entry",
+            RowKind::Total => "total",
+            RowKind::Blank => "blank",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub kind: RowKind,
+    pub cells: Vec<Cell>,
+    /// Currency of the amounts of the row.
+    pub currency: Option<String>,
+}
+
+impl Row {
+    /// A row titling the rows that follow it.
+    pub fn section(title: &str) -> Self {
+        Self {
+            kind: RowKind::Section,
+            cells: vec![Cell::from(title)],
+            currency: None,
+        }
+    }
+
+    pub fn entry(cells: Vec<Cell>) -> Self {
+        Self {
+            kind: RowKind::Entry,
+            cells,
+            currency: None,
+        }
+    }
+
+    pub fn total(cells: Vec<Cell>) -> Self {
+        Self {
+            kind: RowKind::Total,
+            cells,
+            currency: None,
+        }
+    }
+
+    /// An empty line separating the rows in text output.
+    pub fn blank() -> Self {
+        Self {
+            kind: RowKind::Blank,
+            cells: Vec::new(),
+            currency: None,
+        }
+    }
+
+    pub fn with_currency(mut self, currency: &str) -> Self {
+        self.currency = Some(currency.to_string());
+        self
+    }
+
+    /// The cell of the column, with amounts followed by the row currency.
+    fn display(&self, column: usize) -> String {
+        match (self.cells.get(column), &self.currency) {
+            (Some(Cell::Amount(a)), Some(c)) => format!("{:.2} {}", a, c),
+            (Some(cell), _) => cell.display(),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// The cell of the column as shown in text output.
+    fn text(&self, column: usize) -> String {
+        match self.cells.get(column) {
+            Some(Cell::Account { label, .. }) => label.to_string(),
+            _ => self.display(column),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+    pub notes: Vec<String>,
+}
+
+impl Report {
+    pub fn new(columns: &[&str]) -> Self {
+        Self {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, row: Row) {
+        self.rows.push(row);
+    }
+
+    pub fn extend(&mut self, rows: Vec<Row>) {
+        self.rows.extend(rows);
+    }
+
+    pub fn note(&mut self, note: String) {
+        self.notes.push(note);
+    }
+
+    /// Entry and total rows, along with the title of the section they are in.
+    /// A blank row closes the section.
+    fn records(&self) -> Vec<(Option<&str>, &Row)> {
+        let mut section = None;
+        let mut records = Vec::new();
+        for row in &self.rows {
+            match (row.kind, row.cells.first()) {
+                (RowKind::Section, Some(Cell::Text(title))) => section = Some(title.as_str()),
+                (RowKind::Section, _) => {}
+                (RowKind::Blank, _) => section = None,
+                _ => records.push((section, row)),
+            }
+        }
+        records
+    }
+
+    fn has_sections(&self) -> bool {
+        self.rows.iter().any(|r| r.kind == RowKind::Section)
+    }
+
+    fn has_currency(&self) -> bool {
+        self.rows.iter().any(|r| r.currency.is_some())
+    }
+
+    /// Returns true if the column holds amounts, which are aligned to the right.
+    fn is_amount_column(&self, column: usize) -> bool {
+        self.rows.iter().any(|r| {
+            matches!(
+                r.cells.get(column),
+                Some(Cell::Amount(_) | Cell::Quantity(_))
+            )
+        })
+    }
+}
+
+/// Balances of the accounts in each period, by account type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BalanceReport {
+    /// Title of each period column.
+    pub periods: Vec<String>,
+    pub sections: Vec<BalanceSection>,
+    /// Notes on the balances, like commodities without a price.
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceSection {
+    pub account_type: AccountType,
+    pub rows: Vec<BalanceRow>,
+}
+
+/// Balance of a node of the account hierarchy in a currency. Parent accounts
+/// hold the subtotal of the accounts under them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceRow {
+    pub account: String,
+    pub depth: usize,
+    pub currency: String,
+    /// Balance of each period.
+    pub balances: Vec<Decimal>,
+}
+
+/// Report rows of the balance rows. Every row carries the full account path,
+/// while text output names accounts with balances in more than one currency
+/// on their first row only.
+pub fn balance_rows(rows: &[BalanceRow]) -> Vec<Row> {
+    rows.iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let label = match i > 0 && rows[i - 1].account == r.account {
+                true => String::new(),
+                false => {
+                    let segment = r.account.rsplit(ACCOUNT_SEPARATOR).next();
+                    format!("{}{}", "  ".repeat(r.depth), segment.unwrap_or_default())
+                }
+            };
+            let mut cells = vec![Cell::Account {
+                path: r.account.clone(),
+                label,
+            }];
+            cells.extend(r.balances.iter().map(|b| Cell::from(*b)));
+            Row::entry(cells).with_currency(&r.currency)
+        })
+        .collect()
+}
+
+impl From<&BalanceReport> for Report {
+    fn from(balances: &BalanceReport) -> Self {
+        let mut report = Report::new(&["Accounts"]);
+        report.columns.extend(balances.periods.iter().cloned());
+        for section in &balances.sections {
+            report.push(Row::section(section.account_type.to_string().trim_end()));
+            report.extend(balance_rows(&section.rows));
+        }
+        report.notes.extend(balances.notes.iter().cloned());
+        report
+    }
+}
+
+/// Transactions of the journal, in date order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JournalReport {
+    pub entries: Vec<JournalEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub date: NaiveDate,
+    pub payee: Option<String>,
+    pub note: Option<String>,
+    /// True for transactions generated by the ledger, like opening balances.
+    pub generated: bool,
+    pub postings: Vec<JournalPosting>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalPosting {
+    pub account: String,
+    pub amount: Decimal,
+    pub currency: Option<String>,
+}
+
+impl From<&JournalReport> for Report {
+    fn from(journal: &JournalReport) -> Self {
+        let mut report = Report::new(&["Date", "Account", "Amount", "Payee"]);
+        for entry in &journal.entries {
+            for (i, p) in entry.postings.iter().enumerate() {
+                let payee = match i {
+                    0 if entry.generated => {
+                        format!("(generated) {}", entry.note.clone().unwrap_or_default())
+                    }
+                    0 => entry.payee.clone().unwrap_or_default(),
+                    _ => String::new(),
+                };
+                let row = Row::entry(vec![
+                    Cell::from(entry.date),
+                    Cell::from(p.account.as_str()),
+                    Cell::from(p.amount),
+                    Cell::from(payee),
+                ]);
+                match &p.currency {
+                    Some(currency) => report.push(row.with_currency(currency)),
+                    None => report.push(row),
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Accounts of the ledger as a hierarchy.
+#[derive(Debug, Clone, Default)]
+pub struct AccountList {
+    pub accounts: Vec<AccountListEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountListEntry {
+    /// Full colon separated path of the account.
+    pub name: String,
+    pub depth: usize,
+    /// The declared account, none for parent paths that are not declared.
+    pub account: Option<Account>,
+}
+
+impl From<&AccountList> for Report {
+    fn from(list: &AccountList) -> Self {
+        let mut report = Report::new(&["Open", "Type", "Account", "Currency", "Close"]);
+        for entry in &list.accounts {
+            let segment = entry.name.rsplit(ACCOUNT_SEPARATOR).next();
+            let name = format!(
+                "{}{}",
+                "  ".repeat(entry.depth),
+                segment.unwrap_or_default()
+            );
+            let row = match &entry.account {
+                Some(a) => Row::entry(vec![
+                    Cell::from(a.open),
+                    Cell::from(a.account_type.to_string().trim_end()),
+                    Cell::from(name),
+                    Cell::from(a.currency.as_str()),
+                    a.close.map(Cell::from).unwrap_or(Cell::Empty),
+                ]),
+                None => Row::entry(vec![Cell::Empty, Cell::Empty, Cell::from(name)]),
+            };
+            report.push(row);
+        }
+        report
+    }
+}
+
+/// Renders a [Report] into a string.
+pub trait Formatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>>;
+}
+
+/// Aligned columns of plain text.
+pub struct TextFormatter;
+
+impl Formatter for TextFormatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>> {
+        let widths: Vec<usize> = (0..report.columns.len())
+            .map(|i| {
+                report
+                    .rows
+                    .iter()
+                    .filter(|r| matches!(r.kind, RowKind::Entry | RowKind::Total))
+                    .map(|r| r.text(i).chars().count())
+                    .chain([report.columns[i].chars().count()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        // Rows under section titles are indented
+        let indent = match report.has_sections() {
+            true => "  ",
+            false => "",
+        };
+        let line = |cells: Vec<String>| -> String {
+            let line = cells
+                .iter()
+                .enumerate()
+                .map(|(i, c)| match report.is_amount_column(i) {
+                    true => format!("{:>width$}", c, width = widths[i]),
+                    false => format!("{:<width$}", c, width = widths[i]),
+                })
+                .collect::<Vec<String>>()
+                .join("  ");
+            format!("{}{}", indent, line.trim_end())
+        };
+
+        let mut lines = vec![line(report.columns.clone())];
+        for row in &report.rows {
+            match row.kind {
+                RowKind::Section => lines.push(row.display(0)),
+                RowKind::Blank => lines.push(String::new()),
+                _ => lines.push(line(
+                    (0..report.columns.len()).map(|i| row.text(i)).collect(),
+                )),
+            }
+        }
+        if !report.notes.is_empty() {
+            lines.push(String::new());
+            lines.extend(report.notes.iter().cloned());
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+/// Comma separated values, one line per entry and total row. Section titles
+/// and currencies are moved to columns of their own, and the notes follow the
+/// rows as comment lines starting with `#`.
+pub struct CsvFormatter;
+
+impl Formatter for CsvFormatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>> {
+        let mut header: Vec<String> = Vec::new();
+        if report.has_sections() {
+            header.push("Section".to_string());
+        }
+        header.extend(report.columns.iter().cloned());
+        if report.has_currency() {
+            header.push("Currency".to_string());
+        }
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(&header)?;
+        for (section, row) in report.records() {
+            let mut record: Vec<String> = Vec::new();
+            if report.has_sections() {
+                record.push(section.unwrap_or_default().to_string());
+            }
+            record.extend(
+                (0..report.columns.len())
+                    .map(|i| row.cells.get(i).map(|c| c.raw()).unwrap_or_default()),
+            );
+            if report.has_currency() {
+                record.push(row.currency.clone().unwrap_or_default());
+            }
+            writer.write_record(&record)?;
+        }
+        let mut output = String::from_utf8(writer.into_inner()?)?;
+        for note in &report.notes {
+            output.push_str(&format!("# {}\n", note));
+        }
+        Ok(output)
+    }
+}
+
+/// A json object with the columns, the entry and total rows keyed by column
+/// name, and the notes. Amounts are decimal strings, so they keep their exact
+/// value.
+pub struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>> {
+        let rows: Vec<Value> = report
+            .records()
+            .into_iter()
+            .map(|(section, row)| {
+                let values: Map<String, Value> = report
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let value = row.cells.get(i).map(|c| c.json()).unwrap_or_default();
+                        (c.clone(), value)
+                    })
+                    .collect();
+                json!({
+                    "kind": row.kind.name(),
+                    "section": section,
+                    "values": values,
+                    "currency": row.currency,
+                })
+            })
+            .collect();
+        let output = json!({
+            "columns": report.columns,
+            "rows": rows,
+            "notes": report.notes,
+        });
+        Ok(serde_json::to_string_pretty(&output)? + "\n")
+    }
+}
+
+/// A markdown table, with section titles in bold.
+pub struct MarkdownFormatter;
+
+impl Formatter for MarkdownFormatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>> {
+        let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+        let mut lines = vec![
+            line(report.columns.clone()),
+            line(
+                (0..report.columns.len())
+                    .map(|i| match report.is_amount_column(i) {
+                        true => "---:".to_string(),
+                        false => "---".to_string(),
+                    })
+                    .collect(),
+            ),
+        ];
+        for row in &report.rows {
+            let mut cells: Vec<String> = (0..report.columns.len())
+                .map(|i| row.display(i).replace('|', "\\|"))
+                .collect();
+            match row.kind {
+                RowKind::Blank => continue,
+                RowKind::Section => cells[0] = format!("**{}**", cells[0]),
+                _ => {}
+            }
+            lines.push(line(cells));
+        }
+        if !report.notes.is_empty() {
+            lines.push(String::new());
+            lines.extend(report.notes.iter().cloned());
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+/// An html table, with a class for the section and total rows.
+pub struct HtmlFormatter;
+
+impl HtmlFormatter {
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+}
+
+impl Formatter for HtmlFormatter {
+    fn format(&self, report: &Report) -> Result<String, Box<dyn Error>> {
+        let mut lines = vec!["<table>".to_string(), "  <thead>".to_string()];
+        let header: String = report
+            .columns
+            .iter()
+            .map(|c| format!("<th>{}</th>", Self::escape(c)))
+            .collect();
+        lines.push(format!("    <tr>{}</tr>", header));
+        lines.push("  </thead>".to_string());
+        lines.push("  <tbody>".to_string());
+        for row in &report.rows {
+            match row.kind {
+                RowKind::Blank => {}
+                RowKind::Section => lines.push(format!(
+                    "    <tr class=\"section\"><th colspan=\"{}\">{}</th></tr>",
+                    report.columns.len(),
+                    Self::escape(&row.display(0))
+                )),
+                kind => {
+                    let cells: String = (0..report.columns.len())
+                        .map(|i| match report.is_amount_column(i) {
+                            true => format!(
+                                "<td class=\"amount\">{}</td>",
+                                Self::escape(&row.display(i))
+                            ),
+                            false => format!("<td>{}</td>", Self::escape(&row.display(i))),
+                        })
+                        .collect();
+                    match kind {
+                        RowKind::Total => {
+                            lines.push(format!("    <tr class=\"total\">{}</tr>", cells))
+                        }
+                        _ => lines.push(format!("    <tr>{}</tr>", cells)),
+                    }
+                }
+            }
+        }
+        lines.push("  </tbody>".to_string());
+        lines.push("</table>".to_string());
+        for note in &report.notes {
+            lines.push(format!("<p>{}</p>", Self::escape(note)));
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+/// Output format of the reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Csv,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl OutputFormat {
+    pub fn formatter(&self) -> Box<dyn Formatter> {
+        match self {
+            OutputFormat::Text => Box::new(TextFormatter),
+            OutputFormat::Csv => Box::new(CsvFormatter),
+            OutputFormat::Json => Box::new(JsonFormatter),
+            OutputFormat::Markdown => Box::new(MarkdownFormatter),
+            OutputFormat::Html => Box::new(HtmlFormatter),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(input: &str) -> Result<OutputFormat, Self::Err> {
+        match input {
+            "text" => Ok(OutputFormat::Text),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!(
+                "Invalid output {}, expected text, csv, json, markdown or html",
+                input
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let mut report = Report::new(&["Accounts", "Balance"]);
+        report.push(Row::section("Assets"));
+        report.push(
+            Row::entry(vec![
+                Cell::from("Savings Account"),
+                Cell::from(Decimal::new(324500, 2)),
+            ])
+            .with_currency("USD"),
+        );
+        report.push(Row::blank());
+        report.push(
+            Row::total(vec![
+                Cell::from("Total"),
+                Cell::from(Decimal::new(324500, 2)),
+            ])
+            .with_currency("USD"),
+        );
+        report.note("Assets = Liabilities + Equity".to_string());
+        report
+    }
+
+    #[test]
+    fn test_text_formatter() {
+        let expected = concat!(
+            "  Accounts             Balance\n",
+            "Assets\n",
+            "  Savings Account  3245.00 USD\n",
+            "\n",
+            "  Total            3245.00 USD\n",
+            "\n",
+            "Assets = Liabilities + Equity\n",
+        );
+        assert_eq!(TextFormatter.format(&report()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_csv_formatter() {
+        let expected = "\
+Section,Accounts,Balance,Currency
+Assets,Savings Account,3245.00,USD
+,Total,3245.00,USD
+# Assets = Liabilities + Equity
+";
+        assert_eq!(CsvFormatter.format(&report()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_json_formatter() {
+        let output: Value =
+            serde_json::from_str(&JsonFormatter.format(&report()).unwrap()).unwrap();
+        assert_eq!(output["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(output["rows"][0]["section"], "Assets");
+        assert_eq!(output["rows"][0]["values"]["Balance"], "3245.00");
+        assert_eq!(output["rows"][1]["kind"], "total");
+        assert_eq!(output["rows"][1]["section"], Value::Null);
+        assert_eq!(output["notes"][0], "Assets = Liabilities + Equity");
+    }
+
+    #[test]
+    fn test_markdown_formatter() {
+        let output = MarkdownFormatter.format(&report()).unwrap();
+        assert!(output.starts_with("| Accounts | Balance |\n| --- | ---: |\n| **Assets** |  |\n"));
+        assert!(output.contains("| Savings Account | 3245.00 USD |"));
+    }
+
+    #[test]
+    fn test_html_formatter() {
+        let mut report = report();
+        report.push(Row::entry(vec![Cell::from("<Cash>")]));
+        let output = HtmlFormatter.format(&report).unwrap();
+        assert!(output.contains("<tr class=\"section\"><th colspan=\"2\">Assets</th></tr>"));
+        assert!(output.contains("<td>&lt;Cash&gt;</td>"));
+    }
+
+    #[test]
+    fn test_balance_report_into_report() {
+        let balances = BalanceReport {
+            periods: vec!["Balance".to_string()],
+            sections: vec![BalanceSection {
+                account_type: AccountType::Equity,
+                rows: vec![
+                    BalanceRow {
+                        account: "Opening Balances".to_string(),
+                        depth: 0,
+                        currency: "USD".to_string(),
+                        balances: vec![Decimal::from(-1000)],
+                    },
+                    BalanceRow {
+                        account: "Opening Balances".to_string(),
+                        depth: 0,
+                        currency: "BTC".to_string(),
+                        balances: vec![Decimal::new(-56, 2)],
+                    },
+                    BalanceRow {
+                        account: "Opening Balances:USD".to_string(),
+                        depth: 1,
+                        currency: "USD".to_string(),
+                        balances: vec![Decimal::from(-1000)],
+                    },
+                ],
+            }],
+            notes: vec!["No price to convert BTC into ARS, shown in BTC".to_string()],
+        };
+        let report = Report::from(&balances);
+
+        assert_eq!(report.columns, vec!["Accounts", "Balance"]);
+        assert_eq!(report.rows[0], Row::section("Equity"));
+        assert_eq!(report.rows[1].cells[0].text(), "Opening Balances");
+        assert_eq!(report.rows[2].cells[0].text(), "");
+        assert_eq!(report.rows[3].cells[0].text(), "  USD");
+        assert_eq!(report.notes, balances.notes);
+
+        // Machine readable formats name the account of every row in full
+        let expected = "\
+Section,Accounts,Balance,Currency
+Equity,Opening Balances,-1000,USD
+Equity,Opening Balances,-0.56,BTC
+Equity,Opening Balances:USD,-1000,USD
+# No price to convert BTC into ARS, shown in BTC
+";
+        assert_eq!(CsvFormatter.format(&report).unwrap(), expected);
+        let output: Value = serde_json::from_str(&JsonFormatter.format(&report).unwrap()).unwrap();
+        assert_eq!(output["rows"][1]["values"]["Accounts"], "Opening Balances");
+        let output = TextFormatter.format(&report).unwrap();
+        assert!(output.contains("\n  Opening Balances  -1000.00 USD\n"));
+        assert!(output.contains("\n                       -0.56 BTC\n"));
+    }
+
+    #[test]
+    fn test_output_format_from_str() {
+        assert_eq!(OutputFormat::from_str("json"), Ok(OutputFormat::Json));
+        assert!(OutputFormat::from_str("xml").is_err());
+    }
+}
@@ -1,9 +1,9 @@
-//! This module defines the [Transaction] struct.
+//! This module defines the [Transaction] and [Posting] structs.
 //!
 //! Transactions require a **date** (in YYYY-MM-DD format), an **amount** (float or
 //! integer), an **account** and an **offset_account**. The **offset_amount** and **quantity**
 //! can be explicity declared, otherwise it will be set as the inverse of the
-//! amount and to one (1) respectively. **Payee** and **note** are optional fields.
+//! amount times the quantity and to one (1) respectively. **Payee** and **note** are optional fields.
 //!
 //! ```toml
 //! [[transaction]]
@@ -16,23 +16,105 @@
 //! payee = "RESTAURAN X"   # optional
 //! note = "Meal was good"  # optional
 //! ```
+//!
+//! Transactions with more than two legs (a paycheck, a split receipt) are
+//! declared with a **postings** array instead. Each posting requires an
+//! **account** and an **amount**, with an optional **quantity**. One posting
+//! may omit the amount, in which case it is set to the amount that balances
+//! the transaction.
+//!
+//! ```toml
+//! [[transaction]]
+//! date = 2023-11-02
+//! payee = "EMPLOYER"
+//! postings = [
+//!     { account = "Salary", amount = -2300.00 },
+//!     { account = "Taxes", amount = 450.00 },
+//!     { account = "Savings Account" },
+//! ]
+//! ```
+//!
+//! Postings to Stocks, MutualFunds and Holdings accounts can declare the
+//! **cost**, **cost_currency** and **lot** of the units, see [crate::lots].
+//!
+//! Transactions between accounts in different currencies declare the
+//! exchange **rate** of a unit of the account currency into the offset
+//! currency, or the **total** amount in the offset currency, to be checked
+//! against the offset amount. Postings declare them the same way, converting
+//! into the currency of the postings without a rate.
+//!
+//! ```toml
+//! [[transaction]]
+//! date = 2023-10-02
+//! amount = -100.00
+//! account = "Savings Account"
+//! offset_account = "Pesos"
+//! offset_amount = 93000.00
+//! rate = 930.00 # or total = 93000.00
+//! ```
 
+use crate::error::Location;
 use crate::utils::deserialize_date;
 use chrono::prelude::NaiveDate;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Posting {
+    pub account: String,
+    pub amount: Decimal,
+    pub quantity: Decimal,
+    /// Unit cost of the units bought, inferred from the other postings if None.
+    #[serde(skip)]
+    pub cost: Option<Decimal>,
+    #[serde(skip)]
+    pub cost_currency: Option<String>,
+    /// Purchase date of the lot the units are sold from.
+    #[serde(skip)]
+    pub lot: Option<NaiveDate>,
+    /// Exchange rate of a unit of the posting into the currency of the other
+    /// postings.
+    #[serde(skip)]
+    pub rate: Option<Decimal>,
+    /// True if the posting declares a quantity, so the amount is the price of
+    /// each unit.
+    #[serde(skip)]
+    pub per_unit: bool,
+}
+
+impl Posting {
+    pub fn new(account: String, amount: Decimal, quantity: Decimal) -> Self {
+        Self {
+            account: account.replace('"', ""),
+            amount,
+            quantity,
+            cost: None,
+            cost_currency: None,
+            lot: None,
+            rate: None,
+            per_unit: false,
+        }
+    }
+
+    /// The amount the posting adds to the account balance.
+    pub fn value(&self) -> Decimal {
+        self.amount * self.quantity
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Transaction {
     #[serde(deserialize_with = "deserialize_date", skip_serializing)]
     pub date: NaiveDate,
-    pub account: String,
     pub note: Option<String>,
     pub payee: Option<String>,
-    pub quantity: f32,
-    pub amount: f32,
-    pub offset_account: String,
-    pub offset_amount: f32,
+    pub postings: Vec<Posting>,
+    #[serde(skip)]
+    pub location: Option<Location>,
+    /// True for transactions generated by the ledger instead of declared in it.
+    #[serde(skip)]
+    pub generated: bool,
 }
 
 impl fmt::Display for Transaction {
@@ -43,37 +125,68 @@ impl fmt::Display for Transaction {
             self.payee.clone().unwrap_or_default(),
             self.note.clone().unwrap_or_default()
         );
-        let posting = format!(
-            "{:<10}:  {:>} qty: {:<}",
-            self.account, self.amount, self.quantity
-        );
-        let offset = format!("{:<10}: {:>}", self.offset_account, self.offset_amount);
-        write!(f, "{}\n{}\n{}\n", header, posting, offset)
+        writeln!(f, "{}", header)?;
+        for (i, p) in self.postings.iter().enumerate() {
+            if i == 0 {
+                writeln!(
+                    f,
+                    "{:<10}:  {:>} qty: {:<}",
+                    p.account, p.amount, p.quantity
+                )?;
+            } else {
+                writeln!(f, "{:<10}: {:>}", p.account, p.value())?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl Transaction {
+    /// Creates a two legged transaction from an account and an offset account.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         date: NaiveDate,
         account: String,
         payee: Option<String>,
-        quantity: f32,
-        amount: f32,
+        quantity: Decimal,
+        amount: Decimal,
         offset_account: String,
-        offset_amount: f32,
+        offset_amount: Decimal,
+        note: Option<String>,
+    ) -> Self {
+        let postings = vec![
+            Posting::new(account, amount, quantity),
+            Posting::new(offset_account, offset_amount, Decimal::ONE),
+        ];
+        Self::with_postings(date, payee, note, postings)
+    }
+
+    /// Creates a transaction from any number of postings.
+    pub fn with_postings(
+        date: NaiveDate,
+        payee: Option<String>,
         note: Option<String>,
+        postings: Vec<Posting>,
     ) -> Self {
         Self {
-            date: date,
-            account: account.replace('"', ""),
-            payee: payee,
-            quantity: quantity,
-            amount: amount,
-            offset_account: offset_account.replace('"', ""),
-            offset_amount: offset_amount,
-            note: note,
+            date,
+            payee,
+            postings,
+            note,
+            location: None,
+            generated: false,
         }
     }
+
+    /// Returns true if any of the postings is made to the account.
+    pub fn involves(&self, account: &str) -> bool {
+        self.postings.iter().any(|p| p.account == account)
+    }
+
+    /// Sum of all posting values. Zero for a balanced single currency transaction.
+    pub fn sum(&self) -> Decimal {
+        self.postings.iter().map(|p| p.value()).sum()
+    }
 }
 
 #[cfg(test)]
@@ -85,10 +198,10 @@ mod tests {
         let date = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let account = "Account1".to_string();
         let payee = Some("Payee1".to_string());
-        let quantity = 100.0;
-        let amount = 500.0;
+        let quantity = Decimal::from(100);
+        let amount = Decimal::from(500);
         let offset_account = "Account2".to_string();
-        let offset_amount = 500.0;
+        let offset_amount = Decimal::from(500);
         let note = Some("Note1".to_string());
 
         let transaction = Transaction::new(
@@ -103,24 +216,41 @@ mod tests {
         );
 
         assert_eq!(transaction.date, date);
-        assert_eq!(transaction.account, account);
+        assert_eq!(transaction.postings[0].account, account);
         assert_eq!(transaction.payee, payee);
-        assert_eq!(transaction.quantity, quantity);
-        assert_eq!(transaction.amount, amount);
-        assert_eq!(transaction.offset_account, offset_account);
-        assert_eq!(transaction.offset_amount, offset_amount);
+        assert_eq!(transaction.postings[0].quantity, quantity);
+        assert_eq!(transaction.postings[0].amount, amount);
+        assert_eq!(transaction.postings[1].account, offset_account);
+        assert_eq!(transaction.postings[1].amount, offset_amount);
         assert_eq!(transaction.note, note);
     }
 
+    #[test]
+    fn test_transaction_with_postings() {
+        let date = NaiveDate::from_ymd_opt(2023, 11, 2).unwrap();
+        let postings = vec![
+            Posting::new("Salary".to_string(), Decimal::from(-2300), Decimal::ONE),
+            Posting::new("Taxes".to_string(), Decimal::from(450), Decimal::ONE),
+            Posting::new("Savings".to_string(), Decimal::from(1850), Decimal::ONE),
+        ];
+
+        let transaction = Transaction::with_postings(date, None, None, postings);
+
+        assert_eq!(transaction.postings.len(), 3);
+        assert!(transaction.involves("Taxes"));
+        assert!(!transaction.involves("Dining"));
+        assert!(transaction.sum().is_zero());
+    }
+
     #[test]
     fn test_transaction_display() {
         let date = NaiveDate::from_ymd_opt(2023, 10, 13).unwrap();
         let account = "Account1".to_string();
         let payee = Some("Payee1".to_string());
-        let quantity = 100.0;
-        let amount = 500.0;
+        let quantity = Decimal::from(100);
+        let amount = Decimal::from(500);
         let offset_account = "Account2".to_string();
-        let offset_amount = 500.0;
+        let offset_amount = Decimal::from(500);
         let note = Some("Note1".to_string());
 
         let transaction = Transaction::new(
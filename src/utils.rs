@@ -1,39 +1,48 @@
 //! This module contains utility functions.
 
+use crate::error::LedgerError;
 use crate::Ledger;
 use chrono::prelude::*;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer};
-use std::error::Error;
 use std::fs::{self, read_to_string};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use toml::Value;
 
 /// Reads a single toml file from a file path or multiple toml files from
-/// a directory.
-pub fn read_ledger_files(ledger_path: &str) -> Result<Ledger, Box<dyn Error>> {
-    let ledger = match fs::metadata(ledger_path) {
-        Ok(file) => {
-            if file.is_dir() {
-                let mut concatenated_files = String::new();
-                let files = fs::read_dir(ledger_path)?;
-                for f in files {
-                    let f = f?;
-                    let file_path = f.path();
-
-                    if file_path.is_file() && file_path.extension().unwrap_or_default() == "toml" {
-                        let toml_content = read_to_string(file_path).expect("Failed to read file.");
-                        concatenated_files.push_str(&toml_content);
-                    }
-                }
-                Ledger::new(&concatenated_files)
-            } else {
-                let toml_content = fs::read_to_string(ledger_path).expect("Failed to read file.");
-                Ledger::new(&toml_content)
+/// a directory. Each file is parsed separately so errors can point to the
+/// file they were found in.
+pub fn read_ledger_files(ledger_path: &str) -> Result<Ledger, LedgerError> {
+    let io_error = |path: &Path, e: std::io::Error| LedgerError::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    };
+    let path = Path::new(ledger_path);
+    let file = fs::metadata(path).map_err(|e| io_error(path, e))?;
+
+    let mut sources: Vec<(String, String)> = Vec::new();
+    let mut errors: Vec<LedgerError> = Vec::new();
+    if file.is_dir() {
+        let mut file_paths: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| io_error(path, e))?
+            .filter_map(|f| f.ok().map(|f| f.path()))
+            .filter(|f| f.is_file() && f.extension().unwrap_or_default() == "toml")
+            .collect();
+        file_paths.sort();
+
+        for file_path in file_paths {
+            match read_to_string(&file_path) {
+                Ok(content) => sources.push((file_path.display().to_string(), content)),
+                Err(e) => errors.push(io_error(&file_path, e)),
             }
         }
-        Err(_) => Ledger::new(ledger_path),
-    };
-    return ledger;
+    } else {
+        let content = read_to_string(path).map_err(|e| io_error(path, e))?;
+        sources.push((ledger_path.to_string(), content));
+    }
+    LedgerError::from_errors(errors)?;
+    Ledger::from_sources(&sources)
 }
 
 /// Deserialize a NaiveDate from a string
@@ -45,48 +54,132 @@ where
     NaiveDate::parse_from_str(&date_str, "%d/%m/%Y").map_err(serde::de::Error::custom)
 }
 
-/// Parse toml values to f32.
-pub fn parse_value_to_f32<T>(value: &Value, key: &str) -> Option<f32> {
-    let toml_value = value.get(key);
-    let float_value = match toml_value.unwrap_or(&Value::Float(0.0)) {
-        Value::Integer(integer_value) => *integer_value as f32,
-        Value::Float(float_value) => *float_value as f32,
-        _ => panic!("Amount is not an integer or float"),
-    };
-    return Some(float_value);
+/// Parse toml values to Decimal.
+///
+/// Floats are converted through their shortest textual representation, so
+/// `0.1` in the ledger becomes exactly `0.1` and not the nearest binary float.
+pub fn parse_value_to_decimal(value: &Value, key: &str) -> Result<Option<Decimal>, String> {
+    match value.get(key) {
+        Some(Value::Integer(integer_value)) => Ok(Some(Decimal::from(*integer_value))),
+        Some(Value::Float(float_value)) => Decimal::from_str(&float_value.to_string())
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        Some(_) => Err("Amount is not an integer or float".to_string()),
+        None => Ok(None),
+    }
 }
 
-/// Parse toml values to NaiveDate.
-pub fn parse_value_to_naivedate(val: &Value, col: &str) -> Option<NaiveDate> {
-    return NaiveDate::from_str(
-        val.get(col)
-            .expect("{} is a required field")
-            .as_datetime()
-            .expect("Invalid date format")
-            .to_string()
-            .as_ref(),
-    )
-    .ok();
+/// Parse toml non-negative integer values.
+pub fn parse_value_to_integer(value: &Value, key: &str) -> Result<Option<u32>, String> {
+    match value.get(key) {
+        Some(Value::Integer(integer_value)) => {
+            u32::try_from(*integer_value).map(Some).map_err(|_| {
+                format!(
+                    "Invalid value {}, expected a positive integer",
+                    integer_value
+                )
+            })
+        }
+        Some(_) => Err("Expected a positive integer".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Parse toml boolean values.
+pub fn parse_value_to_bool(value: &Value, key: &str) -> Result<Option<bool>, String> {
+    match value.get(key) {
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(v) => Err(format!("Expected true or false, found {}", v)),
+        None => Ok(None),
+    }
+}
+
+/// Parse toml arrays of strings.
+pub fn parse_value_to_strings(value: &Value, key: &str) -> Result<Option<Vec<String>>, String> {
+    match value.get(key) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|i| match i {
+                Value::String(s) => Ok(s.clone()),
+                v => Err(format!("Expected a string, found {}", v)),
+            })
+            .collect::<Result<Vec<String>, String>>()
+            .map(Some),
+        Some(_) => Err("Expected an array of strings".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Parse toml values to NaiveDate. Dates can be toml dates or strings in
+/// YYYY-MM-DD format.
+pub fn parse_value_to_naivedate(val: &Value, col: &str) -> Result<Option<NaiveDate>, String> {
+    let date = match val.get(col) {
+        Some(Value::Datetime(d)) => d.to_string(),
+        Some(Value::String(s)) => s.to_string(),
+        Some(_) => return Err("Invalid date format, expected YYYY-MM-DD".to_string()),
+        None => return Ok(None),
+    };
+    NaiveDate::from_str(&date)
+        .map(Some)
+        .map_err(|_| format!("Invalid date format {}, expected YYYY-MM-DD", date))
 }
 
 /// Parse any string toml value.
-pub fn parse_value<T>(value: &Value, key: &str) -> Option<T>
+pub fn parse_value<T>(value: &Value, key: &str) -> Result<Option<T>, String>
 where
     T: FromStr,
 {
-    value
-        .get(key)
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse().ok())
+    match value.get(key) {
+        Some(Value::String(s)) => s
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value {}", s)),
+        Some(v) => Err(format!("Expected a string, found {}", v)),
+        None => Ok(None),
+    }
 }
 
 /// Map months to quarters.
 pub fn quarter(month: u32) -> u32 {
     match month {
-        1 | 2 | 3 => 1,
-        4 | 5 | 6 => 2,
-        7 | 8 | 9 => 3,
-        10 | 11 | 12 => 4,
+        1..=3 => 1,
+        4..=6 => 2,
+        7..=9 => 3,
+        10..=12 => 4,
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value_to_decimal_is_exact() {
+        let value: Value = toml::from_str("a = 0.1\nb = 0.2\nc = 3").unwrap();
+        let a = parse_value_to_decimal(&value, "a").unwrap().unwrap();
+        let b = parse_value_to_decimal(&value, "b").unwrap().unwrap();
+        let c = parse_value_to_decimal(&value, "c").unwrap().unwrap();
+
+        assert_eq!(a + b, Decimal::from_str("0.3").unwrap());
+        assert_eq!(c, Decimal::from(3));
+    }
+
+    #[test]
+    fn test_parse_value_errors() {
+        let value: Value = toml::from_str("a = \"x\"\nd = 2023-10-10\ns = \"2023-10-11\"").unwrap();
+
+        assert!(parse_value_to_decimal(&value, "a").is_err());
+        assert_eq!(parse_value_to_decimal(&value, "missing"), Ok(None));
+        assert_eq!(
+            parse_value_to_naivedate(&value, "d"),
+            Ok(NaiveDate::from_ymd_opt(2023, 10, 10))
+        );
+        assert_eq!(
+            parse_value_to_naivedate(&value, "s"),
+            Ok(NaiveDate::from_ymd_opt(2023, 10, 11))
+        );
+        assert!(parse_value_to_naivedate(&value, "a").is_err());
+        assert!(parse_value::<String>(&value, "d").is_err());
+    }
+}